@@ -0,0 +1,141 @@
+//! The header put above every replayed comment, since the target shows the migrating
+//! account as its author. The template is set with `comment_header` in the `[push]`
+//! section; `{{` and `}}` stand for literal braces.
+
+use crate::models::Comment;
+
+/// Placeholders a header template may use.
+pub const PLACEHOLDERS: [&str; 5] = ["author", "author_url", "avatar_url", "created_at", "source_url"];
+
+/// The author's avatar, linked name, time and a link back to the source comment. The
+/// name is not an @mention, which would notify namesakes on the target.
+pub const DEFAULT_HEADER: &str =
+    "<img src=\"{avatar_url}\" width=\"20\" height=\"20\" alt=\"\"> **[{author}]({author_url})** commented on [{created_at}]({source_url}):";
+
+/// Placeholders in `template` that are not known, and braces that do not close.
+pub fn unknown_placeholders(template: &str) -> Vec<String> {
+    let mut unknown = Vec::new();
+    expand(template, |name, closed| {
+        if !closed {
+            unknown.push(format!("{{{}", name));
+        } else if !PLACEHOLDERS.contains(&name) {
+            unknown.push(format!("{{{}}}", name));
+        }
+        String::new()
+    });
+    unknown
+}
+
+/// `template` filled in for `comment`, followed by the comment body.
+pub fn with_header(template: &str, comment: &Comment) -> String {
+    let header = expand(template, |name, closed| if closed { placeholder(name, comment) } else { format!("{{{}", name) });
+    match comment.body.as_deref().filter(|body| !body.is_empty()) {
+        Some(body) => format!("{}\n\n{}", header, body),
+        None => header,
+    }
+}
+
+/// Deleted accounts come back as `null`; GitHub shows them as ghost.
+fn placeholder(name: &str, comment: &Comment) -> String {
+    let user = |field: &str| comment.user.as_ref().and_then(|user| user[field].as_str()).map(str::to_string);
+    match name {
+        "author" => user("login").unwrap_or_else(|| "ghost".to_string()),
+        "author_url" => user("html_url").unwrap_or_else(|| "https://github.com/ghost".to_string()),
+        "avatar_url" => user("avatar_url").unwrap_or_else(|| "https://github.com/ghost.png".to_string()),
+        "created_at" => match chrono::DateTime::parse_from_rfc3339(&comment.created_at) {
+            Ok(timestamp) => timestamp.with_timezone(&chrono::Utc).format("%Y-%m-%d %H:%M UTC").to_string(),
+            Err(_) => comment.created_at.clone(),
+        },
+        "source_url" => comment.html_url.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Replaces every `{name}` with `value(name, true)`, and an unclosed brace with the rest
+/// of the template with `value(rest, false)`.
+fn expand(template: &str, mut value: impl FnMut(&str, bool) -> String) -> String {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        expanded.push_str(&rest[..start]);
+        let brace = &rest[start..start + 1];
+        rest = &rest[start + 1..];
+        if let Some(after) = rest.strip_prefix(brace) {
+            expanded.push_str(brace);
+            rest = after;
+        } else if brace == "}" {
+            expanded.push('}');
+        } else {
+            match rest.find('}') {
+                Some(end) => {
+                    expanded.push_str(&value(rest[..end].trim(), true));
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    expanded.push_str(&value(rest, false));
+                    rest = "";
+                }
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::fixtures;
+
+    fn ghost() -> Comment {
+        fixtures::comment(5, 1, "2024-03-04T05:06:07Z")
+    }
+
+    fn octocat() -> Comment {
+        let user = json!({
+            "login": "octocat",
+            "html_url": "https://github.com/octocat",
+            "avatar_url": "https://avatars.githubusercontent.com/u/1",
+        });
+        Comment { user: Some(user), body: Some("Hello".to_string()), ..ghost() }
+    }
+
+    #[test]
+    fn fills_in_placeholders() {
+        let rendered = with_header("{author} ({author_url}, {avatar_url}) at {created_at}: {source_url}", &octocat());
+        assert_eq!(
+            rendered,
+            "octocat (https://github.com/octocat, https://avatars.githubusercontent.com/u/1) at 2024-03-04 05:06 UTC: \
+             https://github.com/o/r/issues/1#issuecomment-5\n\nHello"
+        );
+    }
+
+    #[test]
+    fn keeps_literal_braces() {
+        assert_eq!(with_header("{{author}} is {author}", &ghost()), "{author} is ghost");
+        assert_eq!(with_header("a }} b", &ghost()), "a } b");
+        assert!(unknown_placeholders("{{not a placeholder}}").is_empty());
+    }
+
+    #[test]
+    fn keeps_a_stray_closing_brace() {
+        assert_eq!(with_header("{author} } done", &octocat()), "octocat } done\n\nHello");
+        assert!(unknown_placeholders("} {author}").is_empty());
+    }
+
+    #[test]
+    fn reports_unclosed_and_unknown_placeholders() {
+        assert_eq!(unknown_placeholders("by {author"), ["{author"]);
+        assert_eq!(unknown_placeholders("{when} by {author} {login}"), ["{when}", "{login}"]);
+        assert!(unknown_placeholders(DEFAULT_HEADER).is_empty());
+        // Config validation rejects it; rendering keeps it as it is.
+        assert_eq!(with_header("by {author", &octocat()), "by {author\n\nHello");
+    }
+
+    #[test]
+    fn falls_back_to_ghost_for_deleted_users() {
+        let rendered = with_header("{author} {author_url} {avatar_url}", &ghost());
+        assert_eq!(rendered, "ghost https://github.com/ghost https://github.com/ghost.png");
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs;
+use std::path::PathBuf;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::data;
+use crate::error::{Error, Result};
+
+/// Responses of earlier GET requests with their `ETag` and `Last-Modified` validators,
+/// one file per URL. GitHub answers a matching conditional request with 304, which does
+/// not count against the rate limit, so refetching an unchanged organization is cheap.
+pub struct HttpCache {
+    dir: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// The `rel="next"` link of a list page.
+    pub next: Option<String>,
+    pub body: String,
+}
+
+impl HttpCache {
+    pub fn new(dir: PathBuf) -> Self {
+        HttpCache { dir }
+    }
+
+    /// An unreadable entry is treated as missing; the response is fetched again.
+    pub fn get(&self, url: &Url) -> Option<CachedResponse> {
+        let path = self.path(url);
+        if !path.exists() {
+            return None;
+        }
+        data::read_json::<CachedResponse>(&path).ok().filter(|cached| cached.url == url.as_str())
+    }
+
+    pub fn put(&self, url: &Url, response: &CachedResponse) -> Result<()> {
+        fs::create_dir_all(&self.dir).map_err(|source| Error::io(&self.dir, source))?;
+        data::write_json(self.path(url), response)
+    }
+
+    fn path(&self, url: &Url) -> PathBuf {
+        self.dir.join(format!("{}.json", hex::encode(Sha256::digest(url.as_str()))))
+    }
+}
@@ -0,0 +1,178 @@
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+
+/// Migrates repositories and issues from one GitHub organization to another
+/// GitHub or GitFlic organization.
+#[derive(Debug, Parser)]
+#[command(name = "gh-org-migrator", version)]
+pub struct Cli {
+    /// TOML configuration file [default: gh-org-migrator.toml if it exists].
+    #[arg(long, global = true, env = "GH_ORG_MIGRATOR_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Named profile from the configuration file.
+    #[arg(long, global = true, env = "GH_ORG_MIGRATOR_PROFILE")]
+    pub profile: Option<String>,
+
+    /// Organization to fetch from [env: SOURCE_ORGANIZATION].
+    #[arg(long, global = true)]
+    pub source_org: Option<String>,
+
+    /// Organization to push to or remove from [env: TARGET_ORGANIZATION].
+    #[arg(long, global = true)]
+    pub target_org: Option<String>,
+
+    /// Directory holding one subdirectory of fetched data per organization [env: DATA_DIR] [default: data].
+    #[arg(long, global = true)]
+    pub data_dir: Option<PathBuf>,
+
+    /// Where the target organization lives [env: BACKEND] [default: github].
+    #[arg(long, global = true, value_enum)]
+    pub backend: Option<Backend>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Download repositories and issues of the source organization into the data directory.
+    Fetch(FetchArgs),
+    /// Recreate fetched data in the target organization.
+    Push {
+        #[command(subcommand)]
+        stage: PushStage,
+    },
+    /// Check that every fetched repository exists in the target organization.
+    Verify,
+    /// Write out which target repository and issue every pushed source one became.
+    Export(ExportArgs),
+    /// Delete the fetched repositories from the target organization.
+    Remove {
+        /// Do not ask for confirmation.
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct FetchArgs {
+    /// Which issues to fetch [default: all].
+    #[arg(long, value_enum)]
+    pub state: Option<IssueState>,
+
+    /// Only fetch issues with this label. Repeat to require several labels.
+    #[arg(long = "label")]
+    pub labels: Vec<String>,
+
+    /// Only fetch issues updated at or after this time (RFC 3339 or YYYY-MM-DD).
+    #[arg(long)]
+    pub since: Option<String>,
+
+    /// Also fetch the edit history of edited comments (one GraphQL request per 100 comments).
+    #[arg(long)]
+    pub comment_edits: bool,
+
+    /// Save release metadata only, without downloading release assets.
+    #[arg(long)]
+    pub skip_release_assets: bool,
+
+    /// Do not mirror the git repositories.
+    #[arg(long)]
+    pub skip_clone: bool,
+
+    /// Do not fetch Git LFS objects of the mirrored repositories.
+    #[arg(long)]
+    pub skip_lfs: bool,
+
+    /// Refetch every issue and comment instead of only those updated since the last fetch.
+    #[arg(long)]
+    pub full: bool,
+
+    /// How many repositories to fetch at the same time [default: 4].
+    #[arg(long)]
+    pub jobs: Option<usize>,
+
+    /// How many requests to send to one host at the same time [default: 4].
+    #[arg(long)]
+    pub per_host: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// A table for other tools, or a redirect map for old links.
+    #[arg(long, value_enum, default_value = "json")]
+    pub format: MappingFormat,
+
+    /// File to write to [default: standard output].
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PushStage {
+    /// Create missing repositories.
+    Repos,
+    /// Create missing issues in already existing repositories.
+    Issues {
+        /// Use GitHub's issue import API, which keeps the original timestamps and creates each
+        /// issue together with its comments.
+        #[arg(long)]
+        import: bool,
+    },
+    /// Push the branches and tags of the mirrored repositories in one push per repository.
+    Code {
+        /// Only push refs matching this pattern, such as `refs/heads/main` or `refs/tags/v*`.
+        /// Repeat to push several [default: refs/heads/* and refs/tags/*].
+        #[arg(long = "refs")]
+        refs: Vec<String>,
+    },
+    /// Apply topics, merge settings and default branches, then archive what is archived
+    /// in the source. Run last: archived repositories are read-only.
+    Settings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Github,
+    Gitflic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MappingFormat {
+    Json,
+    Csv,
+    /// An nginx `map` of old paths to new URLs.
+    Nginx,
+    /// Caddy `redir` directives.
+    Caddy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IssueState {
+    Open,
+    Closed,
+    All,
+}
+
+impl IssueState {
+    pub fn name(self) -> &'static str {
+        match self {
+            IssueState::Open => "open",
+            IssueState::Closed => "closed",
+            IssueState::All => "all",
+        }
+    }
+}
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Github => "github",
+            Backend::Gitflic => "gitflic",
+        }
+    }
+}
@@ -0,0 +1,539 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::Deserialize;
+
+use crate::attribution;
+use crate::cli::{Backend, Cli, Command, IssueState, PushStage};
+use crate::data::DataDir;
+use crate::error::{Error, Result};
+use crate::git;
+
+const DEFAULT_CONFIG_FILE: &str = "gh-org-migrator.toml";
+
+/// Contents of `gh-org-migrator.toml`. Top-level keys are defaults for every profile.
+///
+/// ```toml
+/// default_profile = "deep-foundation"
+///
+/// [fetch]
+/// issue_state = "all"
+///
+/// [profiles.deep-foundation]
+/// source_organization = "deep-foundation"
+/// target_organization = "link-foundation"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    default_profile: Option<String>,
+    source_organization: Option<String>,
+    target_organization: Option<String>,
+    data_dir: Option<PathBuf>,
+    backend: Option<Backend>,
+    #[serde(default)]
+    fetch: FetchSection,
+    #[serde(default)]
+    push: PushSection,
+    #[serde(default)]
+    pacing: PacingSection,
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+    source_organization: Option<String>,
+    target_organization: Option<String>,
+    data_dir: Option<PathBuf>,
+    backend: Option<Backend>,
+    #[serde(default)]
+    fetch: FetchSection,
+    #[serde(default)]
+    push: PushSection,
+    #[serde(default)]
+    pacing: PacingSection,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FetchSection {
+    issue_state: Option<IssueState>,
+    labels: Option<Vec<String>>,
+    since: Option<String>,
+    comment_edits: Option<bool>,
+    release_assets: Option<bool>,
+    clone: Option<bool>,
+    lfs: Option<bool>,
+    jobs: Option<usize>,
+    per_host: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PushSection {
+    refs: Option<Vec<String>>,
+    /// Target login by source login, for assignees.
+    #[serde(default)]
+    users: BTreeMap<String, String>,
+    /// Create issues through the issue import API.
+    import: Option<bool>,
+    /// Template of the attribution header above replayed comments.
+    comment_header: Option<String>,
+}
+
+/// Pause between writes per backend, in seconds:
+///
+/// ```toml
+/// [pacing.github]
+/// min_interval = 1
+/// max_interval = 60
+/// ```
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PacingSection {
+    #[serde(default)]
+    github: IntervalSection,
+    #[serde(default)]
+    gitflic: IntervalSection,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IntervalSection {
+    min_interval: Option<u64>,
+    max_interval: Option<u64>,
+}
+
+/// Which issues `fetch` downloads.
+#[derive(Debug, Clone)]
+pub struct FetchOptions {
+    pub issue_state: IssueState,
+    pub labels: Vec<String>,
+    /// Normalized to RFC 3339.
+    pub since: Option<String>,
+    pub comment_edits: bool,
+    pub release_assets: bool,
+    pub clone: bool,
+    pub lfs: bool,
+    /// Ignore the watermarks of the previous fetch.
+    pub full: bool,
+    /// Repositories fetched at the same time.
+    pub jobs: usize,
+    /// Requests sent to one host at the same time.
+    pub per_host: usize,
+}
+
+/// What `push code` pushes, and how `push issues` maps users.
+#[derive(Debug, Clone)]
+pub struct PushOptions {
+    /// Forced refspecs such as `+refs/heads/*:refs/heads/*`.
+    pub refspecs: Vec<String>,
+    /// Target login by source login; logins not listed are kept.
+    pub users: BTreeMap<String, String>,
+    /// Create issues with their timestamps and comments through the issue import API.
+    pub import: bool,
+    /// Template of the header naming the original author above every replayed comment.
+    pub comment_header: String,
+}
+
+/// How far apart `push` spaces its writes to the target backend.
+#[derive(Debug, Clone)]
+pub struct PacingOptions {
+    pub min_interval: Duration,
+    pub max_interval: Duration,
+}
+
+/// Settings resolved from, in order of precedence, command line flags, environment
+/// variables (the same ones the JS scripts read), the selected profile and the
+/// top-level keys of the configuration file. A profile chosen with `--profile` or
+/// `GH_ORG_MIGRATOR_PROFILE` goes before the environment variables.
+#[derive(Debug)]
+pub struct Config {
+    source_organization: Option<String>,
+    target_organization: Option<String>,
+    pub data_dir: PathBuf,
+    pub backend: Backend,
+    pub fetch: FetchOptions,
+    pub push: PushOptions,
+    pub pacing: PacingOptions,
+    github_access_token: Option<String>,
+    gitflic_access_token: Option<String>,
+    gitflic_username: Option<String>,
+}
+
+impl Config {
+    /// Loads and validates the configuration for `cli.command`, reporting every
+    /// problem at once.
+    pub fn load(cli: &Cli) -> Result<Self> {
+        let file = read_config_file(cli.config.as_deref())?;
+
+        let profile_name = cli.profile.clone().or_else(|| file.default_profile.clone());
+        let profile = match &profile_name {
+            Some(name) => file.profiles.get(name).cloned().ok_or_else(|| {
+                let known: Vec<&str> = file.profiles.keys().map(String::as_str).collect();
+                Error::Config(format!("Unknown profile {}. Known profiles: {}.", name, known.join(", ")))
+            })?,
+            None => Profile::default(),
+        };
+        let defaults = Profile {
+            source_organization: file.source_organization,
+            target_organization: file.target_organization,
+            data_dir: file.data_dir,
+            backend: file.backend,
+            fetch: file.fetch,
+            push: file.push,
+            pacing: file.pacing,
+        };
+        let fetch_args = match &cli.command {
+            Command::Fetch(args) => Some(args),
+            _ => None,
+        };
+        let import_flag = matches!(cli.command, Command::Push { stage: PushStage::Issues { import: true } });
+        let push_refs = match &cli.command {
+            Command::Push { stage: PushStage::Code { refs } } if !refs.is_empty() => Some(refs.clone()),
+            _ => None,
+        };
+
+        // A profile asked for by name beats the shared `.env`, which sets the organizations
+        // for the JS scripts; the default profile does not.
+        let explicit_profile = cli.profile.is_some();
+        let env_backend = env_var("BACKEND").map(|name| parse_backend(&name)).transpose()?;
+        let backend = resolve(cli.backend, env_backend, profile.backend, explicit_profile, defaults.backend).unwrap_or(Backend::Github);
+        // GitHub asks for a second between content-creating requests; GitFlic starts slower.
+        let (profile_pacing, default_pacing, min_interval, max_interval) = match backend {
+            Backend::Github => (&profile.pacing.github, &defaults.pacing.github, 1, 60),
+            Backend::Gitflic => (&profile.pacing.gitflic, &defaults.pacing.gitflic, 3, 120),
+        };
+        let pacing = PacingOptions {
+            min_interval: Duration::from_secs(profile_pacing.min_interval.or(default_pacing.min_interval).unwrap_or(min_interval)),
+            max_interval: Duration::from_secs(profile_pacing.max_interval.or(default_pacing.max_interval).unwrap_or(max_interval)),
+        };
+
+        let config = Config {
+            source_organization: resolve(
+                cli.source_org.clone(),
+                env_var("SOURCE_ORGANIZATION").or_else(legacy_organization),
+                profile.source_organization,
+                explicit_profile,
+                defaults.source_organization,
+            ),
+            target_organization: resolve(
+                cli.target_org.clone(),
+                env_var("TARGET_ORGANIZATION"),
+                profile.target_organization,
+                explicit_profile,
+                defaults.target_organization,
+            ),
+            data_dir: resolve(cli.data_dir.clone(), env_var("DATA_DIR").map(PathBuf::from), profile.data_dir, explicit_profile, defaults.data_dir)
+                .unwrap_or_else(|| PathBuf::from("data")),
+            backend,
+            fetch: FetchOptions {
+                issue_state: fetch_args
+                    .and_then(|args| args.state)
+                    .or(profile.fetch.issue_state)
+                    .or(defaults.fetch.issue_state)
+                    .unwrap_or(IssueState::All),
+                labels: fetch_args
+                    .map(|args| args.labels.clone())
+                    .filter(|labels| !labels.is_empty())
+                    .or(profile.fetch.labels)
+                    .or(defaults.fetch.labels)
+                    .unwrap_or_default(),
+                since: fetch_args
+                    .and_then(|args| args.since.clone())
+                    .or(profile.fetch.since)
+                    .or(defaults.fetch.since)
+                    .map(|since| normalize_timestamp(&since))
+                    .transpose()?,
+                comment_edits: fetch_args.is_some_and(|args| args.comment_edits)
+                    || profile.fetch.comment_edits.or(defaults.fetch.comment_edits).unwrap_or(false),
+                release_assets: !fetch_args.is_some_and(|args| args.skip_release_assets)
+                    && profile.fetch.release_assets.or(defaults.fetch.release_assets).unwrap_or(true),
+                clone: !fetch_args.is_some_and(|args| args.skip_clone)
+                    && profile.fetch.clone.or(defaults.fetch.clone).unwrap_or(true),
+                lfs: !fetch_args.is_some_and(|args| args.skip_lfs)
+                    && profile.fetch.lfs.or(defaults.fetch.lfs).unwrap_or(true),
+                full: fetch_args.is_some_and(|args| args.full),
+                jobs: fetch_args.and_then(|args| args.jobs).or(profile.fetch.jobs).or(defaults.fetch.jobs).unwrap_or(4),
+                per_host: fetch_args
+                    .and_then(|args| args.per_host)
+                    .or(profile.fetch.per_host)
+                    .or(defaults.fetch.per_host)
+                    .unwrap_or(4),
+            },
+            push: PushOptions {
+                refspecs: push_refs
+                    .or(profile.push.refs)
+                    .or(defaults.push.refs)
+                    .map(|refs| refs.iter().map(|pattern| refspec(pattern)).collect())
+                    .unwrap_or_else(|| git::REFSPECS.iter().map(|refspec| refspec.to_string()).collect()),
+                // The profile's entries win over the top-level ones.
+                users: defaults.push.users.into_iter().chain(profile.push.users).collect(),
+                import: import_flag || profile.push.import.or(defaults.push.import).unwrap_or(false),
+                comment_header: profile
+                    .push
+                    .comment_header
+                    .or(defaults.push.comment_header)
+                    .unwrap_or_else(|| attribution::DEFAULT_HEADER.to_string()),
+            },
+            pacing,
+            github_access_token: env_var("GITHUB_ACCESS_TOKEN"),
+            gitflic_access_token: env_var("GITFLIC_ACCESS_TOKEN"),
+            gitflic_username: env_var("GITFLIC_USERNAME"),
+        };
+
+        config.validate(&cli.command)?;
+        Ok(config)
+    }
+
+    fn validate(&self, command: &Command) -> Result<()> {
+        let mut problems = Vec::new();
+
+        match &self.source_organization {
+            Some(organization) => check_organization_name("Source", organization, &mut problems),
+            None => problems.push("Source organization is not set: use --source-org, SOURCE_ORGANIZATION or source_organization in a profile.".to_string()),
+        }
+
+        let needs_target = !matches!(command, Command::Fetch(_));
+        match &self.target_organization {
+            Some(organization) if self.backend == Backend::Github => check_organization_name("Target", organization, &mut problems),
+            Some(_) => {}
+            None if needs_target => problems.push("Target organization is not set: use --target-org, TARGET_ORGANIZATION or target_organization in a profile.".to_string()),
+            None => {}
+        }
+
+        check_distinct_organizations(command, self.backend, self.source_organization.as_deref(), self.target_organization.as_deref(), &mut problems);
+
+        if self.fetch.jobs == 0 || self.fetch.per_host == 0 {
+            problems.push("jobs and per_host must be at least 1.".to_string());
+        }
+
+        if self.pacing.min_interval > self.pacing.max_interval {
+            problems.push(format!("min_interval must not exceed max_interval for the {} backend.", self.backend.name()));
+        }
+
+        for refspec in &self.push.refspecs {
+            if !refspec.trim_start_matches('+').starts_with("refs/") {
+                problems.push(format!("Push refs must start with refs/, got {}.", refspec));
+            }
+        }
+
+        let unknown = attribution::unknown_placeholders(&self.push.comment_header);
+        if !unknown.is_empty() {
+            problems.push(format!(
+                "comment_header has unknown placeholders {}; use {}.",
+                unknown.join(", "),
+                attribution::PLACEHOLDERS.map(|name| format!("{{{}}}", name)).join(", ")
+            ));
+        }
+
+        if self.data_dir.is_file() {
+            problems.push(format!("Data directory {} is a file.", self.data_dir.display()));
+        }
+
+        let backend = if needs_target { self.backend } else { Backend::Github };
+        if matches!(command, Command::Remove { .. }) && backend != Backend::Github {
+            problems.push(format!("The remove command does not support the {} backend.", backend.name()));
+        }
+        // `export` only reads the state store.
+        let needs_token = !matches!(command, Command::Export(_));
+        match backend {
+            _ if !needs_token => {}
+            Backend::Github if self.github_access_token.is_none() => problems.push("GITHUB_ACCESS_TOKEN must be set in .env file.".to_string()),
+            Backend::Gitflic if self.gitflic_access_token.is_none() => problems.push("GITFLIC_ACCESS_TOKEN must be set in .env file.".to_string()),
+            _ => {}
+        }
+        // Git over HTTPS on GitFlic wants the account's login next to the token.
+        let pushes_code = matches!(command, Command::Push { stage: PushStage::Code { .. } });
+        if pushes_code && backend == Backend::Gitflic && self.gitflic_username.is_none() {
+            problems.push("GITFLIC_USERNAME must be set in .env file to push code.".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Config(format!("\n  {}", problems.join("\n  "))))
+        }
+    }
+
+    pub fn source_organization(&self) -> Result<&str> {
+        self.source_organization.as_deref().ok_or_else(|| Error::Config("Source organization is not set.".to_string()))
+    }
+
+    pub fn target_organization(&self) -> Result<&str> {
+        self.target_organization.as_deref().ok_or_else(|| Error::Config("Target organization is not set.".to_string()))
+    }
+
+    /// Directory with the fetched data of the source organization.
+    pub fn source_data(&self) -> Result<DataDir> {
+        Ok(DataDir::new(&self.data_dir, self.source_organization()?))
+    }
+
+    pub fn github_access_token(&self) -> Result<&str> {
+        self.github_access_token.as_deref().ok_or_else(|| Error::Auth("GITHUB_ACCESS_TOKEN must be set in .env file.".to_string()))
+    }
+
+    pub fn gitflic_access_token(&self) -> Result<&str> {
+        self.gitflic_access_token.as_deref().ok_or_else(|| Error::Auth("GITFLIC_ACCESS_TOKEN must be set in .env file.".to_string()))
+    }
+
+    pub fn gitflic_username(&self) -> Result<&str> {
+        self.gitflic_username.as_deref().ok_or_else(|| Error::Auth("GITFLIC_USERNAME must be set in .env file.".to_string()))
+    }
+}
+
+fn read_config_file(path: Option<&Path>) -> Result<ConfigFile> {
+    let path = match path {
+        Some(path) => path,
+        None if Path::new(DEFAULT_CONFIG_FILE).exists() => Path::new(DEFAULT_CONFIG_FILE),
+        None => return Ok(ConfigFile::default()),
+    };
+    let text = fs::read_to_string(path).map_err(|source| Error::io(path, source))?;
+    toml::from_str(&text).map_err(|error| Error::Config(format!("Invalid configuration file {}: {}", path.display(), error)))
+}
+
+/// The flag, then the environment and the profile (the profile first if it was chosen
+/// explicitly), then the top-level default.
+fn resolve<T>(flag: Option<T>, env: Option<T>, profile: Option<T>, explicit_profile: bool, default: Option<T>) -> Option<T> {
+    let (first, second) = if explicit_profile { (profile, env) } else { (env, profile) };
+    flag.or(first).or(second).or(default)
+}
+
+/// Empty variables count as unset, like `if (!SOURCE_ORGANIZATION)` in the JS scripts.
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+/// `ORGANIZATION` is what older versions of the Rust, Python and shell fetchers read.
+fn legacy_organization() -> Option<String> {
+    let organization = env_var("ORGANIZATION")?;
+    eprintln!("ORGANIZATION is deprecated, use SOURCE_ORGANIZATION instead.");
+    Some(organization)
+}
+
+/// Accepts RFC 3339 timestamps and plain dates, which mean midnight UTC.
+fn normalize_timestamp(value: &str) -> Result<String> {
+    if let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&chrono::Utc).to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(format!("{}T00:00:00Z", date));
+    }
+    Err(Error::Config(format!("since must be an RFC 3339 timestamp or a YYYY-MM-DD date, got {}.", value)))
+}
+
+/// `refs/heads/*` becomes the forced refspec `+refs/heads/*:refs/heads/*`; full refspecs
+/// are kept as they are.
+fn refspec(pattern: &str) -> String {
+    if pattern.contains(':') {
+        pattern.to_string()
+    } else {
+        format!("+{}:{}", pattern.trim_start_matches('+'), pattern.trim_start_matches('+'))
+    }
+}
+
+fn parse_backend(name: &str) -> Result<Backend> {
+    match name.to_lowercase().as_str() {
+        "github" => Ok(Backend::Github),
+        "gitflic" => Ok(Backend::Gitflic),
+        _ => Err(Error::Config(format!("BACKEND must be github or gitflic, got {}.", name))),
+    }
+}
+
+/// `push` into the source organization would overwrite it and `remove` would delete it.
+/// GitFlic organizations may share the name of the GitHub one.
+fn check_distinct_organizations(command: &Command, backend: Backend, source: Option<&str>, target: Option<&str>, problems: &mut Vec<String>) {
+    let writes = matches!(command, Command::Push { .. } | Command::Remove { .. });
+    if let (true, Backend::Github, Some(source), Some(target)) = (writes, backend, source, target) {
+        // GitHub names are case-insensitive.
+        if source.eq_ignore_ascii_case(target) {
+            problems.push(format!("Source and target organization are both {}; push and remove need a different target.", source));
+        }
+    }
+}
+
+/// GitHub logins: up to 39 alphanumeric characters or single hyphens, not at either end.
+fn check_organization_name(kind: &str, name: &str, problems: &mut Vec<String>) {
+    let valid = !name.is_empty()
+        && name.len() <= 39
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-');
+    if !valid {
+        problems.push(format!("{} organization {:?} is not a valid GitHub organization name.", kind, name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_win_then_environment_then_profile_then_defaults() {
+        let pick = |flag: Option<&'static str>, env, profile, default| resolve(flag, env, profile, false, default);
+        assert_eq!(pick(Some("flag"), Some("env"), Some("profile"), Some("default")), Some("flag"));
+        assert_eq!(pick(None, Some("env"), Some("profile"), Some("default")), Some("env"));
+        assert_eq!(pick(None, None, Some("profile"), Some("default")), Some("profile"));
+        assert_eq!(pick(None, None, None, Some("default")), Some("default"));
+        assert_eq!(pick(None, None, None, None), None);
+    }
+
+    #[test]
+    fn explicit_profile_wins_over_environment() {
+        let pick = |flag: Option<&'static str>, env, profile| resolve(flag, env, profile, true, Some("default"));
+        assert_eq!(pick(None, Some("env"), Some("profile")), Some("profile"));
+        assert_eq!(pick(None, Some("env"), None), Some("env"));
+        assert_eq!(pick(Some("flag"), Some("env"), Some("profile")), Some("flag"));
+    }
+
+    #[test]
+    fn normalizes_timestamps_to_utc() {
+        assert_eq!(normalize_timestamp("2024-01-02").unwrap(), "2024-01-02T00:00:00Z");
+        assert_eq!(normalize_timestamp("2024-01-02T03:04:05Z").unwrap(), "2024-01-02T03:04:05Z");
+        assert_eq!(normalize_timestamp("2024-01-02T03:04:05+02:00").unwrap(), "2024-01-02T01:04:05Z");
+        assert!(normalize_timestamp("yesterday").is_err());
+        assert!(normalize_timestamp("2024-13-01").is_err());
+    }
+
+    #[test]
+    fn expands_ref_patterns_into_forced_refspecs() {
+        assert_eq!(refspec("refs/heads/*"), "+refs/heads/*:refs/heads/*");
+        assert_eq!(refspec("+refs/tags/v*"), "+refs/tags/v*:refs/tags/v*");
+        assert_eq!(refspec("refs/heads/main:refs/heads/trunk"), "refs/heads/main:refs/heads/trunk");
+    }
+
+    #[test]
+    fn rejects_writing_to_the_source_organization() {
+        let problems = |command: &Command, backend: Backend, target: &str| {
+            let mut problems = Vec::new();
+            check_distinct_organizations(command, backend, Some("deep-foundation"), Some(target), &mut problems);
+            problems.len()
+        };
+        let remove = Command::Remove { yes: true };
+        let push = Command::Push { stage: PushStage::Repos };
+        assert_eq!(problems(&remove, Backend::Github, "Deep-Foundation"), 1);
+        assert_eq!(problems(&push, Backend::Github, "deep-foundation"), 1);
+        assert_eq!(problems(&push, Backend::Github, "link-foundation"), 0);
+        assert_eq!(problems(&push, Backend::Gitflic, "deep-foundation"), 0);
+        assert_eq!(problems(&Command::Verify, Backend::Github, "deep-foundation"), 0);
+    }
+
+    #[test]
+    fn checks_organization_names() {
+        let problems = |name: &str| {
+            let mut problems = Vec::new();
+            check_organization_name("Target", name, &mut problems);
+            problems
+        };
+        for valid in ["deep-foundation", "a", "A1-b2", &"x".repeat(39)] {
+            assert!(problems(valid).is_empty(), "{} was rejected", valid);
+        }
+        for invalid in ["", "-lead", "trail-", "under_score", "dot.ted", "with space", &"x".repeat(40)] {
+            assert_eq!(problems(invalid).len(), 1, "{} was accepted", invalid);
+        }
+    }
+}
@@ -0,0 +1,387 @@
+//! The data directory: `<data dir>/<organization>/` with one file per kind of object,
+//! shared with the JS scripts.
+//!
+//! Layout versions:
+//! 1. `orgrepos.json` and `<repo>.issues.json`, written by the first Rust and Python fetchers.
+//! 2. `org.repos.json` and `<repo>.issues.json`, read by every JS push script. Issues
+//!    files contain pull requests too.
+//! 3. Layout 2 plus `manifest.json`, with pull requests moved to `<repo>.pulls.json`
+//!    `<repo>.comments.json` holding comments keyed by issue number and
+//!    `<repo>.reviews.json` holding reviews and review comments keyed by pull request number,
+//!    `<repo>.labels.json`, `<repo>.milestones.json`, `<repo>.releases.json` and the
+//!    release assets under `<repo>/releases/<tag>/`. `<repo>.sync.json` records where
+//!    the last fetch of the repository stopped. `<repo>/` itself is a bare mirror of
+//!    the git repository. `.http-cache/` holds the responses of list requests for
+//!    conditional requests on the next fetch. `state.sqlite` records what `push` created
+//!    in each target organization.
+//! 4. Layout 3 with the bare mirror at `<repo>.git/` and the release assets under
+//!    `<repo>.releases/<tag>/`, so that `<repo>/` is left to the working trees that
+//!    `js/pull.js` clones and `js/push-code-commits.js` pushes.
+//!
+//! All layouts are read; only the current one is written.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{to_string_pretty, Value};
+
+use crate::cache::HttpCache;
+use crate::error::{Error, Result};
+use crate::models::{Comment, Issue, Label, Milestone, PullRequest, PullReviews, Release, Repository};
+use crate::state::StateStore;
+
+pub const LAYOUT_VERSION: u32 = 4;
+const JS_LAYOUT_VERSION: u32 = 2;
+const LEGACY_LAYOUT_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const REPOSITORIES_FILE: &str = "org.repos.json";
+const LEGACY_REPOSITORIES_FILE: &str = "orgrepos.json";
+const HTTP_CACHE_DIR: &str = ".http-cache";
+const STATE_FILE: &str = "state.sqlite";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub layout_version: u32,
+    pub organization: String,
+    pub generator: String,
+    pub updated_at: String,
+}
+
+/// Latest `updated_at` seen per kind of item in the last fetch of a repository, so the
+/// next fetch only asks for what changed after it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    /// The `since` the archived data was fetched with; the watermarks only apply to it.
+    pub since: Option<String>,
+    /// Whether the archive only holds issues in some state or with some labels.
+    #[serde(default)]
+    pub filtered: bool,
+    pub issues_updated_at: Option<String>,
+    pub comments_updated_at: Option<String>,
+    pub review_comments_updated_at: Option<String>,
+}
+
+pub struct DataDir {
+    path: PathBuf,
+    organization: String,
+}
+
+impl DataDir {
+    pub fn new(data_dir: &Path, organization: &str) -> Self {
+        DataDir { path: data_dir.join(organization), organization: organization.to_string() }
+    }
+
+    pub fn organization(&self) -> &str {
+        &self.organization
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn create(&self) -> Result<()> {
+        fs::create_dir_all(&self.path).map_err(|source| Error::io(&self.path, source))
+    }
+
+    /// The layout recorded in the manifest or, for directories written before manifests
+    /// existed, the one guessed from the files present.
+    pub fn layout_version(&self) -> Result<u32> {
+        let manifest_path = self.path.join(MANIFEST_FILE);
+        if manifest_path.exists() {
+            let manifest: Manifest = read_json(&manifest_path)?;
+            if manifest.layout_version > LAYOUT_VERSION {
+                return Err(Error::Config(format!(
+                    "{} uses data layout {}, this version only understands up to {}.",
+                    self.path.display(),
+                    manifest.layout_version,
+                    LAYOUT_VERSION
+                )));
+            }
+            return Ok(manifest.layout_version);
+        }
+        if self.path.join(REPOSITORIES_FILE).exists() {
+            Ok(JS_LAYOUT_VERSION)
+        } else {
+            Ok(LEGACY_LAYOUT_VERSION)
+        }
+    }
+
+    pub fn write_manifest(&self) -> Result<()> {
+        let manifest = Manifest {
+            layout_version: LAYOUT_VERSION,
+            organization: self.organization.clone(),
+            generator: format!("gh-org-migrator {}", env!("CARGO_PKG_VERSION")),
+            updated_at: chrono::Utc::now().to_rfc3339(),
+        };
+        write_json(self.path.join(MANIFEST_FILE), &manifest)
+    }
+
+    pub fn read_repositories(&self) -> Result<Vec<Repository>> {
+        match self.layout_version()? {
+            LEGACY_LAYOUT_VERSION => read_json(self.path.join(LEGACY_REPOSITORIES_FILE)),
+            _ => {
+                let path = self.path.join(REPOSITORIES_FILE);
+                let value: Value = read_json(&path)?;
+                repositories_from_value(value).map_err(|source| Error::serialization(path.display().to_string(), source))
+            }
+        }
+    }
+
+    pub fn write_repositories(&self, repos: &[Repository]) -> Result<()> {
+        write_json(self.path.join(REPOSITORIES_FILE), repos)
+    }
+
+    /// Issues only: pull requests mixed in by older layouts are dropped.
+    pub fn read_issues(&self, repo_name: &str) -> Result<Vec<Issue>> {
+        let mut issues: Vec<Issue> = read_json(self.issues_path(repo_name))?;
+        issues.retain(|issue| !issue.is_pull_request());
+        Ok(issues)
+    }
+
+    pub fn write_issues(&self, repo_name: &str, issues: &[Issue]) -> Result<()> {
+        write_json(self.issues_path(repo_name), issues)
+    }
+
+    pub fn read_pulls(&self, repo_name: &str) -> Result<Vec<PullRequest>> {
+        read_json(self.repo_file(repo_name, "pulls"))
+    }
+
+    pub fn write_pulls(&self, repo_name: &str, pulls: &[PullRequest]) -> Result<()> {
+        write_json(self.repo_file(repo_name, "pulls"), pulls)
+    }
+
+    /// `None` for layouts older than 3, which have no comments file.
+    pub fn read_comments(&self, repo_name: &str) -> Result<Option<BTreeMap<u64, Vec<Comment>>>> {
+        self.read_optional(self.repo_file(repo_name, "comments"))
+    }
+
+    pub fn write_comments(&self, repo_name: &str, comments: &BTreeMap<u64, Vec<Comment>>) -> Result<()> {
+        write_json(self.repo_file(repo_name, "comments"), comments)
+    }
+
+    pub fn read_reviews(&self, repo_name: &str) -> Result<BTreeMap<u64, PullReviews>> {
+        read_json(self.repo_file(repo_name, "reviews"))
+    }
+
+    pub fn write_reviews(&self, repo_name: &str, reviews: &BTreeMap<u64, PullReviews>) -> Result<()> {
+        write_json(self.repo_file(repo_name, "reviews"), reviews)
+    }
+
+    /// `None` for layouts older than 3, which have no labels file.
+    pub fn read_labels(&self, repo_name: &str) -> Result<Option<Vec<Label>>> {
+        self.read_optional(self.repo_file(repo_name, "labels"))
+    }
+
+    pub fn write_labels(&self, repo_name: &str, labels: &[Label]) -> Result<()> {
+        write_json(self.repo_file(repo_name, "labels"), labels)
+    }
+
+    /// `None` for layouts older than 3, which have no milestones file.
+    pub fn read_milestones(&self, repo_name: &str) -> Result<Option<Vec<Milestone>>> {
+        self.read_optional(self.repo_file(repo_name, "milestones"))
+    }
+
+    pub fn write_milestones(&self, repo_name: &str, milestones: &[Milestone]) -> Result<()> {
+        write_json(self.repo_file(repo_name, "milestones"), milestones)
+    }
+
+    pub fn write_releases(&self, repo_name: &str, releases: &[Release]) -> Result<()> {
+        write_json(self.repo_file(repo_name, "releases"), releases)
+    }
+
+    /// `None` if the repository was never fetched incrementally.
+    pub fn read_sync_state(&self, repo_name: &str) -> Result<Option<SyncState>> {
+        self.read_optional(self.repo_file(repo_name, "sync"))
+    }
+
+    pub fn write_sync_state(&self, repo_name: &str, state: &SyncState) -> Result<()> {
+        write_json(self.repo_file(repo_name, "sync"), state)
+    }
+
+    /// `<repo>.releases/<tag>/`, with path separators in the tag replaced so that a tag
+    /// can never point outside the directory.
+    pub fn release_dir(&self, repo_name: &str, tag_name: &str) -> PathBuf {
+        self.path.join(format!("{}.releases", repo_name)).join(safe_file_name(tag_name))
+    }
+
+    /// Cache of GitHub responses, keyed by URL.
+    pub fn http_cache(&self) -> HttpCache {
+        HttpCache::new(self.path.join(HTTP_CACHE_DIR))
+    }
+
+    /// What `push` has done to `organization` on `backend`.
+    pub fn state(&self, backend: &str, organization: &str) -> Result<StateStore> {
+        StateStore::open(&self.path.join(STATE_FILE), backend, organization)
+    }
+
+    /// `<repo>.git/`, the bare git mirror of the repository.
+    pub fn mirror_dir(&self, repo_name: &str) -> PathBuf {
+        self.path.join(format!("{}.git", repo_name))
+    }
+
+    /// The repository `push code` pushes: the mirror or, when the repository was not
+    /// mirrored, the working tree `js/pull.js` cloned into `<repo>/`.
+    pub fn code_dir(&self, repo_name: &str) -> PathBuf {
+        let mirror = self.mirror_dir(repo_name);
+        if mirror.exists() {
+            mirror
+        } else {
+            self.path.join(repo_name)
+        }
+    }
+
+    /// Moves the mirror that layout 3 kept in `<repo>/`, and the release assets inside it,
+    /// to where layout 4 keeps them. Working trees are left alone.
+    pub fn upgrade_repo_dirs(&self, repo_name: &str) -> Result<()> {
+        let old = self.path.join(repo_name);
+        let mirror = self.mirror_dir(repo_name);
+        let bare = old.join("HEAD").is_file() && old.join("objects").is_dir();
+        if !bare || mirror.exists() {
+            return Ok(());
+        }
+        fs::rename(&old, &mirror).map_err(|source| Error::io(&old, source))?;
+
+        let old_releases = mirror.join("releases");
+        let releases = self.path.join(format!("{}.releases", repo_name));
+        if old_releases.is_dir() && !releases.exists() {
+            fs::rename(&old_releases, &releases).map_err(|source| Error::io(&old_releases, source))?;
+        }
+        Ok(())
+    }
+
+    fn read_optional<T: DeserializeOwned>(&self, path: PathBuf) -> Result<Option<T>> {
+        if path.exists() {
+            read_json(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn issues_path(&self, repo_name: &str) -> PathBuf {
+        self.repo_file(repo_name, "issues")
+    }
+
+    /// `<repo>.<kind>.json`
+    fn repo_file(&self, repo_name: &str, kind: &str) -> PathBuf {
+        self.path.join(format!("{}.{}.json", repo_name, kind))
+    }
+}
+
+/// `js/pull-or-update-repositories-2.js` stores `{"pages": [{"repos": [...]}, ...]}`
+/// instead of a plain array.
+fn repositories_from_value(value: Value) -> serde_json::Result<Vec<Repository>> {
+    match value {
+        Value::Object(mut object) if object.contains_key("pages") => {
+            let pages: Vec<Value> = serde_json::from_value(object.remove("pages").unwrap_or_default())?;
+            let mut repos = Vec::new();
+            for mut page in pages {
+                let page_repos: Vec<Repository> = serde_json::from_value(page["repos"].take())?;
+                repos.extend(page_repos);
+            }
+            Ok(repos)
+        }
+        value => serde_json::from_value(value),
+    }
+}
+
+/// Replaces path separators and leading dots so that `name` stays a single path component.
+pub fn safe_file_name(name: &str) -> String {
+    let name: String = name.chars().map(|c| if matches!(c, '/' | '\\' | '\0') { '_' } else { c }).collect();
+    match name.strip_prefix('.') {
+        Some(rest) => format!("_{}", rest),
+        None => name,
+    }
+}
+
+pub fn read_json<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T> {
+    let path = path.as_ref();
+    let json = fs::read_to_string(path).map_err(|source| Error::io(path, source))?;
+    serde_json::from_str(&json).map_err(|source| Error::serialization(path.display().to_string(), source))
+}
+
+pub fn write_json<T: Serialize + ?Sized>(path: impl AsRef<Path>, value: &T) -> Result<()> {
+    let path = path.as_ref();
+    let json = to_string_pretty(value).map_err(|source| Error::serialization(path.display().to_string(), source))?;
+    fs::write(path, json).map_err(|source| Error::io(path, source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn data_dir() -> (tempfile::TempDir, DataDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let data = DataDir::new(dir.path(), "o");
+        data.create().unwrap();
+        (dir, data)
+    }
+
+    fn names(data: &DataDir) -> Vec<String> {
+        data.read_repositories().unwrap().into_iter().map(|repo| repo.name).collect()
+    }
+
+    #[test]
+    fn reads_the_legacy_layout() {
+        let (_dir, data) = data_dir();
+        write_json(data.path().join(LEGACY_REPOSITORIES_FILE), &json!([{ "name": "a" }])).unwrap();
+        assert_eq!(data.layout_version().unwrap(), LEGACY_LAYOUT_VERSION);
+        assert_eq!(names(&data), ["a"]);
+    }
+
+    #[test]
+    fn reads_the_js_layout() {
+        let (_dir, data) = data_dir();
+        write_json(data.path().join(REPOSITORIES_FILE), &json!([{ "name": "a" }, { "name": "b" }])).unwrap();
+        assert_eq!(data.layout_version().unwrap(), JS_LAYOUT_VERSION);
+        assert_eq!(names(&data), ["a", "b"]);
+    }
+
+    #[test]
+    fn reads_paged_repositories() {
+        let (_dir, data) = data_dir();
+        let paged = json!({ "pages": [{ "repos": [{ "name": "a" }] }, { "repos": [{ "name": "b" }, { "name": "c" }] }] });
+        write_json(data.path().join(REPOSITORIES_FILE), &paged).unwrap();
+        assert_eq!(names(&data), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn reads_the_current_layout() {
+        let (_dir, data) = data_dir();
+        data.write_manifest().unwrap();
+        let repos: Vec<Repository> = serde_json::from_value(json!([{ "name": "a" }])).unwrap();
+        data.write_repositories(&repos).unwrap();
+        assert_eq!(data.layout_version().unwrap(), LAYOUT_VERSION);
+        assert_eq!(names(&data), ["a"]);
+    }
+
+    #[test]
+    fn moves_layout_3_mirrors_out_of_the_way() {
+        let (_dir, data) = data_dir();
+        git2::Repository::init_bare(data.path().join("bare")).unwrap();
+        fs::create_dir_all(data.path().join("bare").join("releases").join("v1")).unwrap();
+        git2::Repository::init(data.path().join("tree")).unwrap();
+
+        data.upgrade_repo_dirs("bare").unwrap();
+        data.upgrade_repo_dirs("tree").unwrap();
+
+        assert!(!data.path().join("bare").exists());
+        assert!(data.mirror_dir("bare").join("HEAD").is_file());
+        assert!(data.release_dir("bare", "v1").is_dir());
+        assert_eq!(data.code_dir("bare"), data.mirror_dir("bare"));
+        assert_eq!(data.code_dir("tree"), data.path().join("tree"));
+    }
+
+    #[test]
+    fn rejects_a_newer_layout() {
+        let (_dir, data) = data_dir();
+        let manifest = json!({ "layout_version": LAYOUT_VERSION + 1, "organization": "o", "generator": "later", "updated_at": "" });
+        write_json(data.path().join(MANIFEST_FILE), &manifest).unwrap();
+        write_json(data.path().join(REPOSITORIES_FILE), &json!([])).unwrap();
+        assert!(matches!(data.layout_version(), Err(Error::Config(_))));
+        assert!(data.read_repositories().is_err());
+    }
+}
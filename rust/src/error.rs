@@ -0,0 +1,88 @@
+use std::io;
+use std::path::PathBuf;
+
+/// Every way a fetch can fail. Errors for a single repository are recorded and the
+/// run continues; only errors that make the whole run meaningless abort it.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Configuration error: {0}")]
+    Config(String),
+
+    #[error("Authentication failed: {0}")]
+    Auth(String),
+
+    #[error("Failed to build GitHub client: {0}")]
+    Client(#[source] octocrab::Error),
+
+    #[error("HTTP {status} from {url}: {message}")]
+    Http { status: u16, url: String, message: String },
+
+    #[error("Rate limit exceeded for {url}, resets at unix time {reset}")]
+    RateLimited { url: String, reset: u64 },
+
+    #[error("Secondary rate limit hit for {url}")]
+    SecondaryRateLimited { url: String, retry_after: Option<u64> },
+
+    #[error("GraphQL query failed: {0}")]
+    GraphQl(String),
+
+    #[error("Request to {url} failed: {source}")]
+    Transport { url: String, source: reqwest::Error },
+
+    #[error("Issue import {url} failed: {message}")]
+    Import { url: String, message: String },
+
+    #[error("git {operation} of {url} failed: {source}")]
+    Git { operation: &'static str, url: String, source: git2::Error },
+
+    /// `kept` lists the refs the remote accepted in the same push that could not be put back.
+    #[error("{url} rejected {}{}", join(refs), if kept.is_empty() { String::new() } else { format!("; could not undo {}", join(kept)) })]
+    PushRejected { url: String, refs: Vec<RejectedRef>, kept: Vec<String> },
+
+    #[error("`git lfs {command}` failed: {message}")]
+    Lfs { command: String, message: String },
+
+    #[error("State database {} failed: {source}", path.display())]
+    State { path: PathBuf, source: rusqlite::Error },
+
+    #[error("I/O error on {}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+
+    #[error("Failed to serialize or deserialize {what}: {source}")]
+    Serialization { what: String, source: serde_json::Error },
+
+    #[error("Verification failed: {0}")]
+    Verification(String),
+}
+
+/// A ref the remote refused to update, with the reason it gave.
+#[derive(Debug)]
+pub struct RejectedRef {
+    pub name: String,
+    pub reason: String,
+}
+
+impl std::fmt::Display for RejectedRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.reason)
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Items (usually repositories) that were skipped, with the reason why.
+pub type Failures = Vec<(String, Error)>;
+
+impl Error {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Error::Io { path: path.into(), source }
+    }
+
+    pub fn serialization(what: impl Into<String>, source: serde_json::Error) -> Self {
+        Error::Serialization { what: what.into(), source }
+    }
+}
+
+fn join<T: std::fmt::Display>(items: &[T]) -> String {
+    items.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
@@ -0,0 +1,193 @@
+use std::fs;
+use std::path::Path;
+use reqwest::Url;
+
+use crate::cli::MappingFormat;
+use crate::error::{Error, Failures, Result};
+use crate::state::{Mapping, StateStore};
+
+/// Writes where every pushed repository, issue and comment ended up on the target, as recorded
+/// in the state store.
+pub fn run(state: &StateStore, format: MappingFormat, output: Option<&Path>) -> Result<Failures> {
+    let mappings = state.mappings()?;
+    let text = match format {
+        MappingFormat::Json => {
+            serde_json::to_string_pretty(&mappings).map_err(|source| Error::serialization("mapping", source))? + "\n"
+        }
+        MappingFormat::Csv => csv(&mappings),
+        MappingFormat::Nginx => nginx(&redirects(&mappings)),
+        MappingFormat::Caddy => caddy(&redirects(&mappings)),
+    };
+
+    match output {
+        Some(path) => {
+            fs::write(path, text).map_err(|source| Error::io(path, source))?;
+            println!("Exported {} mappings to {}.", mappings.len(), path.display());
+        }
+        None => print!("{}", text),
+    }
+
+    Ok(Vec::new())
+}
+
+fn csv(mappings: &[Mapping]) -> String {
+    let mut text = String::from("kind,repo,source_id,source_url,target_id,target_url\n");
+    for mapping in mappings {
+        let fields = [
+            Some(&mapping.kind),
+            Some(&mapping.repo),
+            Some(&mapping.source_id),
+            mapping.source_url.as_ref(),
+            mapping.target_id.as_ref(),
+            mapping.target_url.as_ref(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field.map_or("", String::as_str))).collect();
+        text.push_str(&fields.join(","));
+        text.push('\n');
+    }
+    text
+}
+
+/// Quotes fields holding a separator, quote or line break, doubling the quotes (RFC 4180).
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// One old path and the URL it moved to.
+struct Redirect {
+    path: String,
+    url: String,
+    /// Repositories also redirect every page below them, such as files and commits.
+    prefix: bool,
+}
+
+/// Mappings without both URLs cannot be redirected and are left out, as are labels, whose
+/// percent-encoded paths the servers would compare with decoded ones, and comments, whose
+/// URLs differ from their issue's only in the fragment, which browsers do not send.
+/// Issues and milestones come first, so that they win over the prefix of their repository.
+fn redirects(mappings: &[Mapping]) -> Vec<Redirect> {
+    let mut redirects: Vec<Redirect> = mappings
+        .iter()
+        .filter(|mapping| matches!(mapping.kind.as_str(), "repository" | "issue" | "milestone"))
+        .filter_map(|mapping| {
+            let source = Url::parse(mapping.source_url.as_deref()?).ok()?;
+            Some(Redirect {
+                path: source.path().trim_end_matches('/').to_string(),
+                url: mapping.target_url.clone()?.trim_end_matches('/').to_string(),
+                prefix: mapping.kind == "repository",
+            })
+        })
+        .collect();
+    redirects.sort_by_key(|redirect| redirect.prefix);
+    redirects
+}
+
+/// A `map` for the `http` block; the server block redirects with
+/// `if ($migrated_url) { return 301 $migrated_url; }`.
+fn nginx(redirects: &[Redirect]) -> String {
+    let mut text = String::from("# Generated by gh-org-migrator. Include in the http block and add to the server block:\n");
+    text.push_str("#     if ($migrated_url) { return 301 $migrated_url; }\n");
+    text.push_str("map $uri $migrated_url {\n    default \"\";\n");
+    for redirect in redirects {
+        if redirect.prefix {
+            // nginx checks exact keys before regular expressions, whatever their order.
+            text.push_str(&format!(
+                "    \"~^{}(?<migrated_rest>/.*)?$\" \"{}$migrated_rest\";\n",
+                regex_escape(&redirect.path),
+                redirect.url
+            ));
+        } else {
+            text.push_str(&format!("    \"{}\" \"{}\";\n", redirect.path, redirect.url));
+        }
+    }
+    text.push_str("}\n");
+    text
+}
+
+/// Directives for a site block. `route` keeps them in order, so issues are checked before
+/// the repository prefixes.
+fn caddy(redirects: &[Redirect]) -> String {
+    let mut text = String::from("# Generated by gh-org-migrator. Import inside a site block.\n");
+    let mut routes = String::from("route {\n");
+    for (index, redirect) in redirects.iter().enumerate() {
+        if redirect.prefix {
+            let name = format!("migrated_repo_{}", index);
+            text.push_str(&format!("@{} path_regexp {} ^{}(/.*)?$\n", name, name, regex_escape(&redirect.path)));
+            routes.push_str(&format!("    redir @{} {}{{re.{}.1}} 301\n", name, redirect.url, name));
+        } else {
+            routes.push_str(&format!("    redir {} {} 301\n", redirect.path, redirect.url));
+        }
+    }
+    routes.push_str("}\n");
+    text + &routes
+}
+
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(kind: &str, repo: &str, source_url: &str, target_url: &str) -> Mapping {
+        Mapping {
+            kind: kind.to_string(),
+            repo: repo.to_string(),
+            source_id: repo.to_string(),
+            source_url: Some(source_url.to_string()),
+            target_id: Some("1".to_string()),
+            target_url: Some(target_url.to_string()),
+        }
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn escapes_repository_names_in_regexes() {
+        let redirects = redirects(&[mapping("repository", "site.io", "https://github.com/old/site.io", "https://github.com/new/site.io")]);
+
+        let nginx = nginx(&redirects);
+        assert!(nginx.contains(r#""~^/old/site\.io(?<migrated_rest>/.*)?$" "https://github.com/new/site.io$migrated_rest";"#), "{}", nginx);
+
+        let caddy = caddy(&redirects);
+        assert!(caddy.contains(r"@migrated_repo_0 path_regexp migrated_repo_0 ^/old/site\.io(/.*)?$"), "{}", caddy);
+        assert!(caddy.contains("redir @migrated_repo_0 https://github.com/new/site.io{re.migrated_repo_0.1} 301"), "{}", caddy);
+    }
+
+    #[test]
+    fn redirects_issues_before_repository_prefixes() {
+        let mappings = [
+            mapping("repository", "foo", "https://github.com/old/foo", "https://github.com/new/foo"),
+            mapping("label", "foo", "https://github.com/old/foo/labels/bug", "https://github.com/new/foo/labels/bug"),
+            mapping("issue", "foo", "https://github.com/old/foo/issues/12", "https://github.com/new/foo/issues/7"),
+            mapping("comment", "foo", "https://github.com/old/foo/issues/12#issuecomment-5", "https://github.com/new/foo/issues/7#issuecomment-9"),
+        ];
+        let redirects = redirects(&mappings);
+        let paths: Vec<&str> = redirects.iter().map(|redirect| redirect.path.as_str()).collect();
+        assert_eq!(paths, ["/old/foo/issues/12", "/old/foo"]);
+
+        let caddy = caddy(&redirects);
+        let issue = caddy.find("redir /old/foo/issues/12 https://github.com/new/foo/issues/7 301").unwrap();
+        let repository = caddy.find("redir @migrated_repo_1").unwrap();
+        assert!(issue < repository, "{}", caddy);
+    }
+}
@@ -0,0 +1,481 @@
+use std::collections::BTreeMap;
+use futures_util::stream::{self, StreamExt};
+use octocrab::Octocrab;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::cache::HttpCache;
+use crate::cli::IssueState;
+use crate::config::FetchOptions;
+use crate::data::{DataDir, SyncState};
+use crate::error::{Error, Failures, Result};
+use crate::git;
+use crate::github;
+use crate::models::{Comment, Issue, Label, Milestone, PullRequest, PullReviews, Repository, Review, ReviewComment};
+use crate::releases;
+
+/// Fetches all repositories of the organization and then the issues, pull requests,
+/// comments, reviews, labels, milestones and releases of each of them, and mirrors its
+/// git repository.
+/// A repository whose data cannot be fetched is skipped and returned as a failure.
+pub async fn run(octocrab: &Octocrab, token: &str, organization: &str, options: &FetchOptions, data: &DataDir) -> Result<Failures> {
+    data.create()?;
+
+    let repos = fetch_repositories(octocrab, organization, options, data).await?;
+
+    // Every repository writes its own files, so the order in which they finish does not
+    // matter; failures are sorted back into the order of the repository list.
+    let auth = git::Auth::github(token);
+    let mut failures: Vec<(usize, String, Error)> = stream::iter(repos.into_iter().enumerate())
+        .map(|(index, repo)| {
+            let auth = &auth;
+            async move {
+                let mut result = fetch_repository(octocrab, organization, &repo.name, options, data).await;
+                if result.is_ok() && options.clone {
+                    result = clone_repository(auth, organization, &repo, options, data).await;
+                }
+                result.err().map(|error| {
+                    eprintln!("Skipping repository {}: {}", repo.name, error);
+                    (index, repo.name, error)
+                })
+            }
+        })
+        .buffer_unordered(options.jobs)
+        .filter_map(|failure| async move { failure })
+        .collect()
+        .await;
+    failures.sort_by_key(|(index, _, _)| *index);
+    let failures = failures.into_iter().map(|(_, repo_name, error)| (repo_name, error)).collect();
+
+    data.write_manifest()?;
+    println!("Data fetching completed. All data is stored in the {} directory.", data.path().display());
+
+    Ok(failures)
+}
+
+/// The organization's repositories with the details that only `GET /repos/{owner}/{repo}`
+/// returns, such as the merge settings. A repository whose details cannot be fetched
+/// keeps the fields of the list.
+async fn fetch_repositories(octocrab: &Octocrab, organization: &str, options: &FetchOptions, data: &DataDir) -> Result<Vec<Repository>> {
+    println!("Fetching repositories for organization {}...", organization);
+    let cache = data.http_cache();
+    let route = format!("orgs/{}/repos?per_page=100", organization);
+    let listed: Vec<Repository> = github::get_all_pages(octocrab, &route, Some(&cache)).await?;
+
+    let repos: Vec<Repository> = stream::iter(listed)
+        .map(|repo| {
+            let cache = &cache;
+            async move {
+                let route = format!("repos/{}/{}", organization, repo.name);
+                match github::get_cached(octocrab, &route, cache).await {
+                    Ok(details) => details,
+                    Err(error) => {
+                        eprintln!("Could not fetch the settings of repository {}: {}", repo.name, error);
+                        repo
+                    }
+                }
+            }
+        })
+        .buffered(options.jobs)
+        .collect()
+        .await;
+    data.write_repositories(&repos)?;
+    Ok(repos)
+}
+
+/// Mirrors all branches and tags, fetching only what changed when the mirror exists.
+async fn clone_repository(auth: &git::Auth, organization: &str, repo: &Repository, options: &FetchOptions, data: &DataDir) -> Result<()> {
+    println!("Cloning repository {}...", repo.name);
+    let url = repo.clone_url.clone().unwrap_or_else(|| format!("https://github.com/{}/{}.git", organization, repo.name));
+    git::mirror(&url, auth, &data.mirror_dir(&repo.name), repo.default_branch.as_deref(), options.lfs).await?;
+    println!("Repository {} cloned successfully.", repo.name);
+    Ok(())
+}
+
+async fn fetch_repository(octocrab: &Octocrab, organization: &str, repo_name: &str, options: &FetchOptions, data: &DataDir) -> Result<()> {
+    data.upgrade_repo_dirs(repo_name)?;
+    fetch_labels(octocrab, organization, repo_name, data).await?;
+    fetch_milestones(octocrab, organization, repo_name, data).await?;
+    releases::fetch_releases(octocrab, organization, repo_name, options.release_assets, data).await?;
+
+    let mut archive = match read_archive(repo_name, options, data)? {
+        Some(archive) => archive,
+        None => {
+            let filtered = options.issue_state != IssueState::All || !options.labels.is_empty();
+            Archive { sync: SyncState { since: options.since.clone(), filtered, ..SyncState::default() }, ..Archive::default() }
+        }
+    };
+    let sync = &archive.sync;
+
+    let items = fetch_issues(octocrab, organization, repo_name, options, sync.issues_updated_at.as_deref().or(sync.since.as_deref()), data).await?;
+    let pulls = fetch_pulls(octocrab, organization, repo_name, &items, data).await?;
+    let comments =
+        fetch_comments(octocrab, organization, repo_name, options, sync.comments_updated_at.as_deref().or(sync.since.as_deref()), data).await?;
+    let since = sync.review_comments_updated_at.as_deref().or(sync.since.as_deref());
+    let (reviews, review_comments) = fetch_reviews(octocrab, organization, repo_name, since, &pulls, data).await?;
+
+    archive.merge(items, pulls, comments, reviews, review_comments);
+    archive.write(repo_name, data)
+}
+
+/// Issues, pull requests, comments and reviews of one repository: those of earlier fetches
+/// with what changed since merged in.
+#[derive(Default)]
+struct Archive {
+    sync: SyncState,
+    issues: BTreeMap<u64, Issue>,
+    pulls: BTreeMap<u64, PullRequest>,
+    comments: BTreeMap<u64, Vec<Comment>>,
+    reviews: BTreeMap<u64, PullReviews>,
+}
+
+/// The data of the previous fetch, if this one may only add what changed since. A fetch
+/// filtered by state or labels cannot tell which archived issues stopped matching, so it
+/// always starts over, as does one with a different `since`.
+fn read_archive(repo_name: &str, options: &FetchOptions, data: &DataDir) -> Result<Option<Archive>> {
+    if options.full || options.issue_state != IssueState::All || !options.labels.is_empty() {
+        return Ok(None);
+    }
+    let Some(sync) = data.read_sync_state(repo_name)? else {
+        return Ok(None);
+    };
+    if sync.filtered || sync.since != options.since {
+        return Ok(None);
+    }
+
+    Ok(Some(Archive {
+        sync,
+        issues: data.read_issues(repo_name)?.into_iter().map(|issue| (issue.number, issue)).collect(),
+        pulls: data.read_pulls(repo_name)?.into_iter().map(|pull| (pull.number, pull)).collect(),
+        comments: data.read_comments(repo_name)?.unwrap_or_default(),
+        reviews: data.read_reviews(repo_name)?,
+    }))
+}
+
+impl Archive {
+    /// Replaces archived items by number or id and moves the watermarks to the newest
+    /// `updated_at` fetched. Comments are only kept for archived issues and pull requests.
+    fn merge(
+        &mut self,
+        items: Vec<Issue>,
+        pulls: Vec<PullRequest>,
+        comments: Vec<Comment>,
+        reviews: BTreeMap<u64, Vec<Review>>,
+        review_comments: Vec<ReviewComment>,
+    ) {
+        advance(&mut self.sync.issues_updated_at, items.iter().filter_map(|item| item.updated_at.as_deref()));
+        advance(&mut self.sync.comments_updated_at, comments.iter().map(|comment| comment.updated_at.as_str()));
+        advance(&mut self.sync.review_comments_updated_at, review_comments.iter().map(|comment| comment.updated_at.as_str()));
+
+        self.issues.extend(items.into_iter().filter(|item| !item.is_pull_request()).map(|issue| (issue.number, issue)));
+        self.pulls.extend(pulls.into_iter().map(|pull| (pull.number, pull)));
+
+        for comment in comments {
+            let Some(number) = comment.issue_number().filter(|number| self.issues.contains_key(number) || self.pulls.contains_key(number)) else {
+                continue;
+            };
+            upsert(self.comments.entry(number).or_default(), comment, |comment| comment.id);
+        }
+
+        for (number, reviews) in reviews {
+            self.reviews.entry(number).or_default().reviews = reviews;
+        }
+        for comment in review_comments {
+            let Some(number) = comment.pull_number().filter(|number| self.pulls.contains_key(number)) else {
+                continue;
+            };
+            upsert(&mut self.reviews.entry(number).or_default().comments, comment, |comment| comment.id);
+        }
+    }
+
+    /// Newest first, like the issues endpoint returns them.
+    fn write(&self, repo_name: &str, data: &DataDir) -> Result<()> {
+        let issues: Vec<Issue> = self.issues.values().rev().cloned().collect();
+        let pulls: Vec<PullRequest> = self.pulls.values().rev().cloned().collect();
+        data.write_issues(repo_name, &issues)?;
+        data.write_pulls(repo_name, &pulls)?;
+        data.write_comments(repo_name, &self.comments)?;
+        data.write_reviews(repo_name, &self.reviews)?;
+        data.write_sync_state(repo_name, &self.sync)
+    }
+}
+
+/// RFC 3339 timestamps in UTC compare correctly as strings.
+fn advance<'a>(watermark: &mut Option<String>, timestamps: impl Iterator<Item = &'a str>) {
+    if let Some(latest) = timestamps.max() {
+        if watermark.as_deref().is_none_or(|current| latest > current) {
+            *watermark = Some(latest.to_string());
+        }
+    }
+}
+
+/// Replaces the item with the same id or appends it.
+fn upsert<T>(items: &mut Vec<T>, item: T, id: impl Fn(&T) -> u64) {
+    match items.iter_mut().find(|existing| id(existing) == id(&item)) {
+        Some(existing) => *existing = item,
+        None => items.push(item),
+    }
+}
+
+async fn fetch_labels(octocrab: &Octocrab, organization: &str, repo_name: &str, data: &DataDir) -> Result<()> {
+    println!("Fetching labels for repository {}...", repo_name);
+    let route = format!("repos/{}/{}/labels?per_page=100", organization, repo_name);
+    let labels: Vec<Label> = github::get_all_pages(octocrab, &route, Some(&data.http_cache())).await?;
+    data.write_labels(repo_name, &labels)
+}
+
+async fn fetch_milestones(octocrab: &Octocrab, organization: &str, repo_name: &str, data: &DataDir) -> Result<()> {
+    println!("Fetching milestones for repository {}...", repo_name);
+    let route = format!("repos/{}/{}/milestones?state=all&per_page=100", organization, repo_name);
+    let milestones: Vec<Milestone> = github::get_all_pages(octocrab, &route, Some(&data.http_cache())).await?;
+    data.write_milestones(repo_name, &milestones)
+}
+
+/// The issues endpoint only returns open issues unless `state` is given, so the filter
+/// is always spelled out. It also returns pull requests, which `fetch_pulls` fetches in
+/// full; all items are returned.
+async fn fetch_issues(
+    octocrab: &Octocrab,
+    organization: &str,
+    repo_name: &str,
+    options: &FetchOptions,
+    since: Option<&str>,
+    data: &DataDir,
+) -> Result<Vec<Issue>> {
+    match since {
+        Some(since) => println!("Fetching issues for repository {} updated since {}...", repo_name, since),
+        None => println!("Fetching issues for repository {}...", repo_name),
+    }
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    query.append_pair("state", options.issue_state.name()).append_pair("per_page", "100");
+    if !options.labels.is_empty() {
+        query.append_pair("labels", &options.labels.join(","));
+    }
+    if let Some(since) = since {
+        query.append_pair("since", since);
+    }
+    let route = format!("repos/{}/{}/issues?{}", organization, repo_name, query.finish());
+    github::get_all_pages(octocrab, &route, uncached_since(&data.http_cache(), since)).await
+}
+
+/// Fetches every pull request among `items` one by one: only the single pull request
+/// endpoint returns the merge state and `merged_by`.
+async fn fetch_pulls(octocrab: &Octocrab, organization: &str, repo_name: &str, items: &[Issue], data: &DataDir) -> Result<Vec<PullRequest>> {
+    let pull_items: Vec<&Issue> = items.iter().filter(|item| item.is_pull_request()).collect();
+    println!("Fetching {} pull requests for repository {}...", pull_items.len(), repo_name);
+
+    let mut pulls: Vec<PullRequest> = Vec::with_capacity(pull_items.len());
+    for item in pull_items {
+        let route = format!("repos/{}/{}/pulls/{}", organization, repo_name, item.number);
+        pulls.push(github::get_cached(octocrab, &route, &data.http_cache()).await?);
+    }
+
+    Ok(pulls)
+}
+
+/// Fetches the conversation comments of the whole repository at once.
+async fn fetch_comments(
+    octocrab: &Octocrab,
+    organization: &str,
+    repo_name: &str,
+    options: &FetchOptions,
+    since: Option<&str>,
+    data: &DataDir,
+) -> Result<Vec<Comment>> {
+    println!("Fetching comments for repository {}...", repo_name);
+    let route = format!("repos/{}/{}/issues/comments?{}", organization, repo_name, chronological_query(since));
+    let mut comments: Vec<Comment> = github::get_all_pages(octocrab, &route, uncached_since(&data.http_cache(), since)).await?;
+
+    if options.comment_edits {
+        fetch_comment_edits(octocrab, &mut comments).await?;
+    }
+
+    Ok(comments)
+}
+
+/// Fetches the reviews of every pull request, by pull request number, and the inline
+/// review comments of the whole repository.
+async fn fetch_reviews(
+    octocrab: &Octocrab,
+    organization: &str,
+    repo_name: &str,
+    since: Option<&str>,
+    pulls: &[PullRequest],
+    data: &DataDir,
+) -> Result<(BTreeMap<u64, Vec<Review>>, Vec<ReviewComment>)> {
+    println!("Fetching reviews for repository {}...", repo_name);
+    let mut reviews = BTreeMap::new();
+    for pull in pulls {
+        let route = format!("repos/{}/{}/pulls/{}/reviews?per_page=100", organization, repo_name, pull.number);
+        reviews.insert(pull.number, github::get_all_pages(octocrab, &route, Some(&data.http_cache())).await?);
+    }
+
+    let route = format!("repos/{}/{}/pulls/comments?{}", organization, repo_name, chronological_query(since));
+    let comments: Vec<ReviewComment> = github::get_all_pages(octocrab, &route, uncached_since(&data.http_cache(), since)).await?;
+
+    Ok((reviews, comments))
+}
+
+/// The cache for a route filtered by `since`, which is none: the watermark moves with
+/// every fetch, so the next fetch asks for a different URL and each cached response
+/// would be left behind for good.
+fn uncached_since<'a>(cache: &'a HttpCache, since: Option<&str>) -> Option<&'a HttpCache> {
+    since.is_none().then_some(cache)
+}
+
+/// Query for the repository-wide comment endpoints: oldest first, optionally only
+/// comments updated at or after `since`.
+fn chronological_query(since: Option<&str>) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    query.append_pair("sort", "created").append_pair("direction", "asc").append_pair("per_page", "100");
+    if let Some(since) = since {
+        query.append_pair("since", since);
+    }
+    query.finish()
+}
+
+const COMMENT_EDITS_QUERY: &str = "
+query($ids: [ID!]!) {
+  nodes(ids: $ids) {
+    ... on IssueComment {
+      id
+      userContentEdits(first: 100) {
+        nodes { createdAt editedAt deletedAt editor { login } diff }
+      }
+    }
+  }
+}";
+
+/// The REST API has no edit history, so it is looked up through GraphQL for comments
+/// whose `updated_at` differs from `created_at`, 100 comments per request.
+async fn fetch_comment_edits(octocrab: &Octocrab, comments: &mut [Comment]) -> Result<()> {
+    #[derive(Deserialize)]
+    struct Data {
+        nodes: Vec<Option<Node>>,
+    }
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Node {
+        id: String,
+        user_content_edits: Edits,
+    }
+    #[derive(Deserialize)]
+    struct Edits {
+        nodes: Vec<Value>,
+    }
+
+    let mut edited: Vec<&mut Comment> = comments.iter_mut().filter(|comment| comment.updated_at != comment.created_at).collect();
+    for batch in edited.chunks_mut(100) {
+        let ids: Vec<&str> = batch.iter().map(|comment| comment.node_id.as_str()).collect();
+        let data: Data = github::graphql(octocrab, COMMENT_EDITS_QUERY, json!({ "ids": ids })).await?;
+        let mut edits: BTreeMap<String, Vec<Value>> =
+            data.nodes.into_iter().flatten().map(|node| (node.id, node.user_content_edits.nodes)).collect();
+        for comment in batch.iter_mut() {
+            comment.edits = edits.remove(&comment.node_id);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{comment, issue};
+
+    fn archive(issues: Vec<Issue>, watermark: &str) -> Archive {
+        Archive {
+            sync: SyncState { issues_updated_at: Some(watermark.to_string()), ..SyncState::default() },
+            issues: issues.into_iter().map(|issue| (issue.number, issue)).collect(),
+            ..Archive::default()
+        }
+    }
+
+    fn options() -> FetchOptions {
+        FetchOptions {
+            issue_state: IssueState::All,
+            labels: Vec::new(),
+            since: None,
+            comment_edits: false,
+            release_assets: false,
+            clone: false,
+            lfs: false,
+            full: false,
+            jobs: 1,
+            per_host: 1,
+        }
+    }
+
+    #[test]
+    fn merge_replaces_updated_issues() {
+        let issues = vec![issue(1, "old", "2024-01-01T00:00:00Z"), issue(2, "kept", "2024-01-01T00:00:00Z")];
+        let mut archive = archive(issues, "2024-01-01T00:00:00Z");
+        archive.merge(vec![issue(1, "new", "2024-02-01T00:00:00Z")], Vec::new(), Vec::new(), BTreeMap::new(), Vec::new());
+
+        assert_eq!(archive.issues.len(), 2);
+        assert_eq!(archive.issues[&1].title, "new");
+        assert_eq!(archive.issues[&2].title, "kept");
+        assert_eq!(archive.sync.issues_updated_at.as_deref(), Some("2024-02-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn merge_drops_comments_of_unknown_issues() {
+        let mut archive = archive(vec![issue(1, "known", "2024-01-01T00:00:00Z")], "2024-01-01T00:00:00Z");
+        let comments = vec![comment(10, 1, "2024-01-02T00:00:00Z"), comment(20, 2, "2024-01-03T00:00:00Z")];
+        archive.merge(Vec::new(), Vec::new(), comments, BTreeMap::new(), Vec::new());
+
+        assert_eq!(archive.comments.keys().collect::<Vec<_>>(), [&1]);
+        assert_eq!(archive.comments[&1].iter().map(|comment| comment.id).collect::<Vec<_>>(), [10]);
+        // The watermark covers every comment fetched, kept or not.
+        assert_eq!(archive.sync.comments_updated_at.as_deref(), Some("2024-01-03T00:00:00Z"));
+    }
+
+    #[test]
+    fn merge_replaces_comments_by_id() {
+        let mut archive = archive(vec![issue(1, "known", "2024-01-01T00:00:00Z")], "2024-01-01T00:00:00Z");
+        archive.comments.insert(1, vec![comment(10, 1, "2024-01-01T00:00:00Z"), comment(11, 1, "2024-01-01T00:00:00Z")]);
+        archive.merge(Vec::new(), Vec::new(), vec![comment(10, 1, "2024-03-01T00:00:00Z")], BTreeMap::new(), Vec::new());
+
+        let comments = &archive.comments[&1];
+        assert_eq!(comments.iter().map(|comment| comment.id).collect::<Vec<_>>(), [10, 11]);
+        assert_eq!(comments[0].updated_at, "2024-03-01T00:00:00Z");
+    }
+
+    #[test]
+    fn watermark_never_moves_backwards() {
+        let mut watermark = Some("2024-05-01T00:00:00Z".to_string());
+        advance(&mut watermark, ["2024-04-01T00:00:00Z", "2024-03-01T00:00:00Z"].into_iter());
+        assert_eq!(watermark.as_deref(), Some("2024-05-01T00:00:00Z"));
+
+        advance(&mut watermark, std::iter::empty());
+        assert_eq!(watermark.as_deref(), Some("2024-05-01T00:00:00Z"));
+
+        advance(&mut watermark, ["2024-06-01T00:00:00Z"].into_iter());
+        assert_eq!(watermark.as_deref(), Some("2024-06-01T00:00:00Z"));
+
+        let mut empty = None;
+        advance(&mut empty, ["2024-01-01T00:00:00Z"].into_iter());
+        assert_eq!(empty.as_deref(), Some("2024-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn read_archive_refetches_filtered_or_other_since() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = DataDir::new(dir.path(), "o");
+        std::fs::create_dir_all(dir.path().join("o")).unwrap();
+        archive(vec![issue(1, "archived", "2024-01-01T00:00:00Z")], "2024-01-01T00:00:00Z").write("r", &data).unwrap();
+
+        let archived = read_archive("r", &options(), &data).unwrap().expect("an unfiltered archive is reused");
+        assert_eq!(archived.issues.len(), 1);
+        assert!(read_archive("r", &FetchOptions { full: true, ..options() }, &data).unwrap().is_none());
+        assert!(read_archive("r", &FetchOptions { labels: vec!["bug".to_string()], ..options() }, &data).unwrap().is_none());
+
+        let since = Some("2023-01-01T00:00:00Z".to_string());
+        assert!(read_archive("r", &FetchOptions { since: since.clone(), ..options() }, &data).unwrap().is_none());
+        data.write_sync_state("r", &SyncState { since: since.clone(), ..SyncState::default() }).unwrap();
+        assert!(read_archive("r", &FetchOptions { since, ..options() }, &data).unwrap().is_some());
+
+        data.write_sync_state("r", &SyncState { filtered: true, ..SyncState::default() }).unwrap();
+        assert!(read_archive("r", &options(), &data).unwrap().is_none());
+    }
+}
@@ -0,0 +1,31 @@
+//! Minimal GitHub objects for tests, as the REST API would return them.
+
+use serde_json::json;
+
+use crate::models::{Comment, Issue};
+
+/// An open issue of `o/r`.
+pub fn issue(number: u64, title: &str, updated_at: &str) -> Issue {
+    serde_json::from_value(json!({
+        "number": number,
+        "node_id": format!("I_{}", number),
+        "title": title,
+        "html_url": format!("https://github.com/o/r/issues/{}", number),
+        "state": "open",
+        "updated_at": updated_at,
+    }))
+    .unwrap()
+}
+
+/// A comment on issue `issue_number` of `o/r` by a deleted user, without a body.
+pub fn comment(id: u64, issue_number: u64, updated_at: &str) -> Comment {
+    serde_json::from_value(json!({
+        "id": id,
+        "node_id": format!("IC_{}", id),
+        "html_url": format!("https://github.com/o/r/issues/{}#issuecomment-{}", issue_number, id),
+        "issue_url": format!("https://api.github.com/repos/o/r/issues/{}", issue_number),
+        "created_at": updated_at,
+        "updated_at": updated_at,
+    }))
+    .unwrap()
+}
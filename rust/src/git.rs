@@ -0,0 +1,443 @@
+//! Clone, fetch and push through libgit2 with the credentials given per remote, so runs
+//! do not depend on the host's git version or its credential configuration. Only Git
+//! LFS, which libgit2 does not implement, still runs the `git lfs` command, and only
+//! when it is installed.
+//!
+//! libgit2 is a C library; gitoxide, the pure-Rust alternative, cannot push yet.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use git2::{Cred, CredentialType, ErrorCode, FetchOptions, FetchPrune, Oid, PushOptions, RemoteCallbacks, Repository};
+use tokio::process::Command;
+
+use crate::error::{Error, RejectedRef, Result};
+use crate::limits;
+
+/// Branches and tags; GitHub's read-only `refs/pull/*` are left out because no target
+/// accepts them.
+pub const REFSPECS: [&str; 2] = ["+refs/heads/*:refs/heads/*", "+refs/tags/*:refs/tags/*"];
+
+/// Credentials for one remote, handed to libgit2 when the remote asks for them.
+#[derive(Clone)]
+pub struct Auth {
+    username: String,
+    password: String,
+}
+
+impl Auth {
+    pub fn github(token: &str) -> Self {
+        Auth { username: "x-access-token".to_string(), password: token.to_string() }
+    }
+
+    /// GitFlic takes the account's login with an access token as the password.
+    pub fn gitflic(username: &str, token: &str) -> Self {
+        Auth { username: username.to_string(), password: token.to_string() }
+    }
+
+    /// The same credentials as an HTTP header, for `git lfs`.
+    fn header(&self) -> String {
+        format!("Authorization: Basic {}", STANDARD.encode(format!("{}:{}", self.username, self.password)))
+    }
+}
+
+/// What happened to each ref of a push, by ref name on the remote.
+#[derive(Debug, Default)]
+pub struct PushReport {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+impl PushReport {
+    pub fn changed(&self) -> bool {
+        !self.created.is_empty() || !self.updated.is_empty()
+    }
+}
+
+/// Mirrors every branch and tag of `url` into `dir`, which becomes a bare repository.
+/// An existing repository is updated in place. `url` may also be a local path.
+pub async fn mirror(url: &str, auth: &Auth, dir: &Path, default_branch: Option<&str>, lfs: bool) -> Result<()> {
+    let _permit = limits::acquire(url).await;
+    let git_dir = {
+        let (url, auth, dir, default_branch) = (url.to_string(), auth.clone(), dir.to_path_buf(), default_branch.map(str::to_string));
+        tokio::task::spawn_blocking(move || fetch_mirror(&url, &auth, &dir, default_branch.as_deref()))
+            .await
+            .expect("git fetch panicked")?
+    };
+
+    if lfs {
+        if lfs_installed().await {
+            lfs_command(&git_dir, url, Some(auth), &["fetch", "--all", "origin"]).await?;
+        } else {
+            eprintln!("git-lfs is not installed, skipping LFS objects of {}.", url);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the git directory: `<dir>/.git` for a working tree, `<dir>` for a bare repository.
+fn fetch_mirror(url: &str, auth: &Auth, dir: &Path, default_branch: Option<&str>) -> Result<PathBuf> {
+    let git_error = |operation| move |source| Error::Git { operation, url: url.to_string(), source };
+
+    // The repository is initialized in place, so that a mirror left by an interrupted run
+    // is picked up instead of cloned again.
+    let repo = match Repository::open(dir) {
+        Ok(repo) => repo,
+        Err(error) if error.code() == ErrorCode::NotFound => Repository::init_bare(dir).map_err(git_error("init"))?,
+        Err(source) => return Err(git_error("open")(source)),
+    };
+
+    let mut remote = match repo.find_remote("origin") {
+        Ok(remote) if remote.url() == Some(url) => remote,
+        Ok(_) => {
+            repo.remote_set_url("origin", url).map_err(git_error("set-url"))?;
+            repo.find_remote("origin").map_err(git_error("set-url"))?
+        }
+        Err(_) => {
+            // Branches are mirrored as branches instead of `refs/remotes/origin/*`.
+            repo.remote_with_fetch("origin", url, REFSPECS[0]).map_err(git_error("remote add"))?;
+            repo.remote_add_fetch("origin", REFSPECS[1]).map_err(git_error("remote add"))?;
+            repo.find_remote("origin").map_err(git_error("remote add"))?
+        }
+    };
+
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks(Some(auth))).prune(FetchPrune::On);
+    remote.fetch(&REFSPECS, Some(&mut options), None).map_err(git_error("fetch"))?;
+
+    if let (true, Some(branch)) = (repo.is_bare(), default_branch) {
+        repo.set_head(&format!("refs/heads/{}", branch)).map_err(git_error("set-head"))?;
+    }
+
+    Ok(repo.path().to_path_buf())
+}
+
+/// Pushes the refs matching `refspecs` from the repository in `dir` to `url`, all or
+/// nothing: either every ref is updated or none is.
+pub async fn push(dir: &Path, url: &str, auth: Option<&Auth>, refspecs: &[String]) -> Result<PushReport> {
+    let _permit = limits::acquire(url).await;
+    let (report, git_dir) = {
+        let (dir, url, auth, refspecs) = (dir.to_path_buf(), url.to_string(), auth.cloned(), refspecs.to_vec());
+        tokio::task::spawn_blocking(move || push_refs(&dir, &url, auth.as_ref(), &refspecs))
+            .await
+            .expect("git push panicked")?
+    };
+
+    // The repository only holds LFS pointers; the objects themselves are pushed separately.
+    if git_dir.join("lfs").join("objects").is_dir() && lfs_installed().await {
+        lfs_command(&git_dir, url, auth, &["push", "--all", url]).await?;
+    }
+
+    Ok(report)
+}
+
+/// libgit2 cannot ask the remote for an atomic push, so all or nothing is kept in two
+/// steps. Before anything is sent, libgit2 refuses the whole push if one of the unforced
+/// refs would not fast-forward. Refs the remote then rejects on its own, such as
+/// protected branches, are reported, and the refs it accepted in the same push are put
+/// back where they were.
+fn push_refs(dir: &Path, url: &str, auth: Option<&Auth>, refspecs: &[String]) -> Result<(PushReport, PathBuf)> {
+    let git_error = |operation| move |source| Error::Git { operation, url: url.to_string(), source };
+
+    let repo = Repository::open(dir).map_err(|error| match error.code() {
+        ErrorCode::NotFound => Error::Config(format!("Repository directory not found: {}", dir.display())),
+        _ => git_error("open")(error),
+    })?;
+
+    // Pattern refspecs are expanded here, so that every ref is pushed under its own name.
+    let mut updates = Vec::new();
+    let mut destinations = Vec::new();
+    for reference in repo.references().map_err(git_error("for-each-ref"))? {
+        let reference = reference.map_err(git_error("for-each-ref"))?;
+        // Symbolic refs such as `HEAD` have no target of their own.
+        let (Some(name), Some(_)) = (reference.name(), reference.target()) else {
+            continue;
+        };
+        if let Some((refspec, destination)) = refspecs.iter().find_map(|refspec| Some((refspec, map_ref(refspec, name)?))) {
+            let force = if refspec.starts_with('+') { "+" } else { "" };
+            updates.push(format!("{}{}:{}", force, name, destination));
+            destinations.push(destination);
+        }
+    }
+
+    let mut report = PushReport::default();
+    if updates.is_empty() {
+        return Ok((report, repo.path().to_path_buf()));
+    }
+
+    // libgit2 tells which refs it is about to change, with their old ids, right before
+    // sending them; refs it leaves out are already up to date.
+    let mut changes: HashMap<String, Oid> = HashMap::new();
+    let mut rejected = Vec::new();
+    {
+        let mut callbacks = callbacks(auth);
+        callbacks.push_negotiation(|negotiated| {
+            for update in negotiated {
+                if update.src() != update.dst() {
+                    changes.insert(String::from_utf8_lossy(update.dst_refname_bytes()).into_owned(), update.src());
+                }
+            }
+            Ok(())
+        });
+        callbacks.push_update_reference(|name, status| {
+            if let Some(reason) = status {
+                rejected.push(RejectedRef { name: name.to_string(), reason: reason.to_string() });
+            }
+            Ok(())
+        });
+        let mut options = PushOptions::new();
+        options.remote_callbacks(callbacks);
+        let mut remote = repo.remote_anonymous(url).map_err(git_error("push"))?;
+        remote.push(&updates, Some(&mut options)).map_err(git_error("push"))?;
+    }
+    if !rejected.is_empty() {
+        let accepted: Vec<(String, Oid)> = changes.into_iter().filter(|(name, _)| !rejected.iter().any(|rejected| &rejected.name == name)).collect();
+        let kept = roll_back(&repo, url, auth, &accepted);
+        return Err(Error::PushRejected { url: url.to_string(), refs: rejected, kept });
+    }
+
+    for destination in destinations {
+        match changes.get(&destination) {
+            Some(old) if old.is_zero() => report.created.push(destination),
+            Some(_) => report.updated.push(destination),
+            None => report.unchanged.push(destination),
+        }
+    }
+    Ok((report, repo.path().to_path_buf()))
+}
+
+/// Puts every ref in `accepted` back to its old id on the remote, deleting the ones that
+/// did not exist. Returns the refs that could not be put back: those whose old commit
+/// is not in the local repository, or all of them if the remote refuses.
+fn roll_back(repo: &Repository, url: &str, auth: Option<&Auth>, accepted: &[(String, Oid)]) -> Vec<String> {
+    // A refspec can only take a local ref as its source, so old ids get temporary refs.
+    let mut updates = Vec::new();
+    let mut temporary = Vec::new();
+    let mut kept = Vec::new();
+    for (index, (name, old)) in accepted.iter().enumerate() {
+        if old.is_zero() {
+            updates.push(format!(":{}", name));
+            continue;
+        }
+        let source = format!("refs/gh-org-migrator/rollback/{}", index);
+        match repo.reference(&source, *old, true, "roll back a rejected push") {
+            Ok(reference) => {
+                temporary.push(reference);
+                updates.push(format!("+{}:{}", source, name));
+            }
+            Err(_) => kept.push(name.clone()),
+        }
+    }
+
+    let mut rejected = Vec::new();
+    if !updates.is_empty() {
+        let mut callbacks = callbacks(auth);
+        callbacks.push_update_reference(|name, status| {
+            if status.is_some() {
+                rejected.push(name.to_string());
+            }
+            Ok(())
+        });
+        let mut options = PushOptions::new();
+        options.remote_callbacks(callbacks);
+        let pushed = repo.remote_anonymous(url).and_then(|mut remote| remote.push(&updates, Some(&mut options)));
+        if pushed.is_err() {
+            for (name, _) in accepted {
+                if !kept.contains(name) {
+                    kept.push(name.clone());
+                }
+            }
+        }
+    }
+    kept.extend(rejected);
+    for mut reference in temporary {
+        let _ = reference.delete();
+    }
+    kept
+}
+
+/// Where `name` goes under `refspec` (`[+]<src>:<dst>`, each side with at most one `*`),
+/// or `None` if the refspec does not match it.
+fn map_ref(refspec: &str, name: &str) -> Option<String> {
+    let (source, destination) = refspec.trim_start_matches('+').split_once(':')?;
+    match source.split_once('*') {
+        Some((prefix, suffix)) => {
+            let matched = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            Some(destination.replacen('*', matched, 1))
+        }
+        None => (name == source).then(|| destination.to_string()),
+    }
+}
+
+/// Answers the first credentials request with `auth`. Git's own credential helpers are
+/// never asked, so runs do not depend on how the host is set up. A second request means
+/// the credentials were refused, and is failed instead of looping.
+fn callbacks(auth: Option<&Auth>) -> RemoteCallbacks<'_> {
+    let mut attempts = 0;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |_url, _username, allowed| {
+        attempts += 1;
+        match auth {
+            _ if attempts > 1 => Err(git2::Error::from_str("the remote refused the credentials")),
+            Some(auth) if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) => Cred::userpass_plaintext(&auth.username, &auth.password),
+            Some(_) => Err(git2::Error::from_str("the remote asked for credentials other than a token")),
+            None => Err(git2::Error::from_str("the remote asked for credentials, but none were given")),
+        }
+    });
+    callbacks
+}
+
+async fn lfs_installed() -> bool {
+    Command::new("git")
+        .args(["lfs", "version"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .is_ok_and(|status| status.success())
+}
+
+/// Runs `git lfs` in `git_dir`. The credentials are passed as a header scoped to `url`
+/// in the environment, so they never show up in the process list.
+async fn lfs_command(git_dir: &Path, url: &str, auth: Option<&Auth>, args: &[&str]) -> Result<()> {
+    let mut command = Command::new("git");
+    command.current_dir(git_dir).arg("lfs").args(args).env("GIT_TERMINAL_PROMPT", "0").stdin(Stdio::null());
+    if let Some(auth) = auth {
+        command
+            .env("GIT_CONFIG_COUNT", "1")
+            .env("GIT_CONFIG_KEY_0", format!("http.{}.extraheader", url))
+            .env("GIT_CONFIG_VALUE_0", auth.header());
+    }
+
+    let lfs_error = |message: String| Error::Lfs { command: args.join(" "), message };
+    let output = command.output().await.map_err(|error| lfs_error(error.to_string()))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(lfs_error(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+
+    /// A bare repository with the branches `main` and `feature/x` and the tag `v1`.
+    fn source_repository(dir: &Path) {
+        let repo = Repository::init_bare(dir).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let tree = repo.find_tree(repo.treebuilder(None).unwrap().write().unwrap()).unwrap();
+        let first = repo.commit(Some("refs/heads/main"), &signature, &signature, "first", &tree, &[]).unwrap();
+        let first = repo.find_commit(first).unwrap();
+        let second = repo.commit(Some("refs/heads/feature/x"), &signature, &signature, "second", &tree, &[&first]).unwrap();
+        repo.tag_lightweight("v1", repo.find_commit(second).unwrap().as_object(), false).unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+    }
+
+    #[test]
+    fn mirrors_and_pushes_local_repositories() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, mirror, target) = (dir.path().join("source"), dir.path().join("mirror"), dir.path().join("target"));
+        source_repository(&source);
+        Repository::init_bare(&target).unwrap();
+        let refspecs: Vec<String> = REFSPECS.iter().map(|refspec| refspec.to_string()).collect();
+        let auth = Auth::github("unused");
+
+        fetch_mirror(source.to_str().unwrap(), &auth, &mirror, Some("main")).unwrap();
+        let mirrored = Repository::open(&mirror).unwrap();
+        for name in ["refs/heads/main", "refs/heads/feature/x", "refs/tags/v1"] {
+            assert!(mirrored.find_reference(name).is_ok(), "{} was not mirrored", name);
+        }
+
+        let target_url = target.to_str().unwrap();
+        let (first, _) = push_refs(&mirror, target_url, None, &refspecs).unwrap();
+        let mut created = first.created.clone();
+        created.sort();
+        assert_eq!(created, ["refs/heads/feature/x", "refs/heads/main", "refs/tags/v1"]);
+        assert!(first.updated.is_empty() && first.unchanged.is_empty());
+
+        let (second, _) = push_refs(&mirror, target_url, None, &refspecs).unwrap();
+        assert!(!second.changed());
+        assert_eq!(second.unchanged.len(), 3);
+    }
+
+    /// Commits on top of `branch` in the bare repository at `dir`, or starts it over when
+    /// `rewrite` is set, and returns the new commit.
+    fn commit(dir: &Path, branch: &str, rewrite: bool) -> Oid {
+        let repo = Repository::open(dir).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let tree = repo.find_tree(repo.treebuilder(None).unwrap().write().unwrap()).unwrap();
+        let reference = format!("refs/heads/{}", branch);
+        let parent = repo.find_reference(&reference).unwrap().peel_to_commit().unwrap();
+        let parents = if rewrite { vec![] } else { vec![&parent] };
+        let oid = repo.commit(None, &signature, &signature, "next", &tree, &parents).unwrap();
+        repo.reference(&reference, oid, true, "test").unwrap();
+        oid
+    }
+
+    fn target_ref(dir: &Path, name: &str) -> Option<Oid> {
+        Repository::open(dir).unwrap().find_reference(name).ok().and_then(|reference| reference.target())
+    }
+
+    #[test]
+    fn refuses_the_whole_push_when_a_ref_would_not_fast_forward() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, target) = (dir.path().join("source"), dir.path().join("target"));
+        source_repository(&source);
+        Repository::init_bare(&target).unwrap();
+        let target_url = target.to_str().unwrap();
+        let refspecs = vec!["refs/heads/*:refs/heads/*".to_string()];
+        push_refs(&source, target_url, None, &refspecs).unwrap();
+        let (main, feature) = (target_ref(&target, "refs/heads/main"), target_ref(&target, "refs/heads/feature/x"));
+
+        commit(&source, "main", true);
+        commit(&source, "feature/x", false);
+        assert!(push_refs(&source, target_url, None, &refspecs).is_err());
+        assert_eq!(target_ref(&target, "refs/heads/main"), main);
+        assert_eq!(target_ref(&target, "refs/heads/feature/x"), feature);
+    }
+
+    #[test]
+    fn rolls_back_accepted_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, target) = (dir.path().join("source"), dir.path().join("target"));
+        source_repository(&source);
+        Repository::init_bare(&target).unwrap();
+        let target_url = target.to_str().unwrap();
+        let refspecs: Vec<String> = REFSPECS.iter().map(|refspec| refspec.to_string()).collect();
+        push_refs(&source, target_url, None, &refspecs).unwrap();
+        let old = target_ref(&target, "refs/heads/main").unwrap();
+
+        let new = commit(&source, "main", false);
+        let repo = Repository::open(&source).unwrap();
+        repo.tag_lightweight("v2", &repo.find_object(new, None).unwrap(), false).unwrap();
+        push_refs(&source, target_url, None, &refspecs).unwrap();
+        assert_eq!(target_ref(&target, "refs/heads/main"), Some(new));
+
+        let accepted = [("refs/heads/main".to_string(), old), ("refs/tags/v2".to_string(), Oid::zero())];
+        assert!(roll_back(&repo, target_url, None, &accepted).is_empty());
+        assert_eq!(target_ref(&target, "refs/heads/main"), Some(old));
+        assert_eq!(target_ref(&target, "refs/tags/v2"), None);
+        assert!(repo.find_reference("refs/gh-org-migrator/rollback/0").is_err());
+
+        // A commit the local repository does not have cannot be put back.
+        let unknown = Oid::from_str("1234567890123456789012345678901234567890").unwrap();
+        let kept = roll_back(&repo, target_url, None, &[("refs/heads/main".to_string(), unknown)]);
+        assert_eq!(kept, ["refs/heads/main"]);
+    }
+
+    #[test]
+    fn maps_refs_through_refspecs() {
+        assert_eq!(map_ref("+refs/heads/*:refs/heads/*", "refs/heads/feature/x").as_deref(), Some("refs/heads/feature/x"));
+        assert_eq!(map_ref("refs/tags/v*:refs/tags/release-*", "refs/tags/v1.2").as_deref(), Some("refs/tags/release-1.2"));
+        assert_eq!(map_ref("refs/heads/main:refs/heads/trunk", "refs/heads/main").as_deref(), Some("refs/heads/trunk"));
+        assert_eq!(map_ref("refs/heads/main:refs/heads/main", "refs/heads/mainline"), None);
+        assert_eq!(map_ref("+refs/heads/*:refs/heads/*", "refs/tags/v1"), None);
+        assert_eq!(map_ref("refs/heads/*", "refs/heads/main"), None);
+    }
+}
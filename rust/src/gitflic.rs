@@ -0,0 +1,77 @@
+use reqwest::{Client, Method, Url};
+use serde_json::json;
+
+use crate::error::{Error, Result};
+use crate::github;
+use crate::models::Repository;
+
+pub const GITFLIC_API_HOST: &str = "api.gitflic.ru";
+
+/// Minimal GitFlic REST client covering the project endpoints the migrator needs.
+pub struct GitFlic {
+    client: Client,
+    token: String,
+}
+
+impl GitFlic {
+    pub fn new(token: &str) -> Self {
+        GitFlic { client: Client::new(), token: token.to_string() }
+    }
+
+    /// The id of the project, or `None` if there is none with that alias.
+    pub async fn project_id(&self, owner: &str, repo_name: &str) -> Result<Option<String>> {
+        let alias = gitflicify_repository_name(repo_name);
+        let url = api_url(&format!("project/{}/{}", owner, alias))?;
+        match github::send(self.request(Method::GET, &url), &url).await {
+            Ok(response) => {
+                let project: serde_json::Value = github::parse_json(response, &url).await?;
+                Ok(Some(github::id_of(&project)).filter(|_| project["alias"] == alias.as_str()))
+            }
+            Err(Error::Http { status: 404, .. }) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Creates a company-owned project with the same visibility, description and language,
+    /// and returns its id.
+    pub async fn create_project(&self, owner: &str, repo: &Repository) -> Result<String> {
+        let alias = gitflicify_repository_name(&repo.name);
+        let url = api_url("project")?;
+        let body = json!({
+            "ownerAlias": owner,
+            "ownerAliasType": "COMPANY",
+            "title": alias,
+            "alias": alias,
+            "isPrivate": repo.private,
+            "description": repo.description,
+            "language": repo.language,
+        });
+        let response = github::send(self.request(Method::POST, &url).json(&body), &url).await?;
+        let created: serde_json::Value = github::parse_json(response, &url).await?;
+        Ok(github::id_of(&created))
+    }
+
+    fn request(&self, method: Method, url: &Url) -> reqwest::RequestBuilder {
+        self.client.request(method, url.clone()).header("Authorization", format!("token {}", self.token))
+    }
+}
+
+fn api_url(route: &str) -> Result<Url> {
+    Url::parse(&format!("https://{}/{}", GITFLIC_API_HOST, route))
+        .map_err(|error| Error::Config(format!("Invalid GitFlic route {}: {}", route, error)))
+}
+
+/// GitFlic aliases only allow latin and cyrillic letters, `_` and `-`.
+pub fn gitflicify_repository_name(repo_name: &str) -> String {
+    repo_name
+        .chars()
+        .map(|c| {
+            let lower = c.to_lowercase().next().unwrap_or(c);
+            if lower.is_ascii_lowercase() || ('а'..='я').contains(&lower) || c == '_' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
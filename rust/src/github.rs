@@ -0,0 +1,367 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use octocrab::Octocrab;
+use reqwest::header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER};
+use reqwest::{Method, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::cache::{CachedResponse, HttpCache};
+use crate::error::{Error, Result};
+use crate::limits;
+use crate::models::Milestone;
+
+/// Attempts per request, counting the first one.
+const MAX_ATTEMPTS: u32 = 5;
+/// First wait after a server error; doubled on every retry.
+const SERVER_ERROR_DELAY: Duration = Duration::from_secs(2);
+/// GitHub asks to wait at least a minute after a secondary rate limit without `retry-after`.
+const SECONDARY_RATE_LIMIT_DELAY: Duration = Duration::from_secs(60);
+
+pub fn client(github_access_token: &str) -> Result<Octocrab> {
+    Octocrab::builder().personal_token(github_access_token.to_string()).build().map_err(Error::Client)
+}
+
+pub async fn get<T: DeserializeOwned>(octocrab: &Octocrab, route: &str) -> Result<T> {
+    let url = absolute_url(octocrab, route)?;
+    let response = send(octocrab.request_builder(url.clone(), Method::GET), &url).await?;
+    parse_json(response, &url).await
+}
+
+/// Like `get`, but answered from `cache` when the resource has not changed.
+pub async fn get_cached<T: DeserializeOwned>(octocrab: &Octocrab, route: &str, cache: &HttpCache) -> Result<T> {
+    let url = absolute_url(octocrab, route)?;
+    let (body, _) = get_conditional(octocrab, &url, Some(cache)).await?;
+    serde_json::from_str(&body).map_err(|source| Error::serialization(url.as_str(), source))
+}
+
+pub async fn post<B: Serialize + ?Sized, T: DeserializeOwned>(octocrab: &Octocrab, route: &str, body: &B) -> Result<T> {
+    let url = absolute_url(octocrab, route)?;
+    let response = send(octocrab.request_builder(url.clone(), Method::POST).json(body), &url).await?;
+    parse_json(response, &url).await
+}
+
+pub async fn patch<B: Serialize + ?Sized, T: DeserializeOwned>(octocrab: &Octocrab, route: &str, body: &B) -> Result<T> {
+    let url = absolute_url(octocrab, route)?;
+    let response = send(octocrab.request_builder(url.clone(), Method::PATCH).json(body), &url).await?;
+    parse_json(response, &url).await
+}
+
+pub async fn put<B: Serialize + ?Sized, T: DeserializeOwned>(octocrab: &Octocrab, route: &str, body: &B) -> Result<T> {
+    let url = absolute_url(octocrab, route)?;
+    let response = send(octocrab.request_builder(url.clone(), Method::PUT).json(body), &url).await?;
+    parse_json(response, &url).await
+}
+
+pub async fn delete(octocrab: &Octocrab, route: &str) -> Result<()> {
+    let url = absolute_url(octocrab, route)?;
+    send(octocrab.request_builder(url.clone(), Method::DELETE), &url).await?;
+    Ok(())
+}
+
+/// Runs a GraphQL query and returns its `data`. GraphQL reports errors with a 200
+/// status, so the `errors` array is checked explicitly.
+pub async fn graphql<T: DeserializeOwned>(octocrab: &Octocrab, query: &str, variables: serde_json::Value) -> Result<T> {
+    #[derive(serde::Deserialize)]
+    struct Response<T> {
+        data: Option<T>,
+        #[serde(default)]
+        errors: Vec<GraphQlError>,
+    }
+    #[derive(serde::Deserialize)]
+    struct GraphQlError {
+        message: String,
+    }
+
+    let body = serde_json::json!({ "query": query, "variables": variables });
+    let response: Response<T> = post(octocrab, "graphql", &body).await?;
+    if !response.errors.is_empty() {
+        let messages: Vec<String> = response.errors.into_iter().map(|error| error.message).collect();
+        return Err(Error::GraphQl(messages.join("; ")));
+    }
+    response.data.ok_or_else(|| Error::GraphQl("response has no data".to_string()))
+}
+
+/// Returns `false` instead of an error when the resource answers with 404. The body is
+/// not read; some endpoints answer 204 without one.
+pub async fn exists(octocrab: &Octocrab, route: &str) -> Result<bool> {
+    let url = absolute_url(octocrab, route)?;
+    match send(octocrab.request_builder(url.clone(), Method::GET), &url).await {
+        Ok(_) => Ok(true),
+        Err(Error::Http { status: 404, .. }) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Fetches every page of a list endpoint, following the `Link: rel="next"` header
+/// the same way `Octocrab::get_page` does, but keeping the HTTP status so that
+/// failures can be reported precisely. Pages that have not changed since they were
+/// stored in `cache` are read from it.
+pub async fn get_all_pages<T: DeserializeOwned>(octocrab: &Octocrab, route: &str, cache: Option<&HttpCache>) -> Result<Vec<T>> {
+    let mut next = Some(absolute_url(octocrab, route)?);
+    let mut items: Vec<T> = Vec::new();
+
+    while let Some(url) = next {
+        let (body, next_url) = get_conditional(octocrab, &url, cache).await?;
+        next = next_url;
+        let page: Vec<T> = serde_json::from_str(&body).map_err(|source| Error::serialization(url.as_str(), source))?;
+        items.extend(page);
+    }
+
+    Ok(items)
+}
+
+/// GETs `url` with the validators of its cached response, if any, and returns the body
+/// and the next page link, from the cache when the server answers 304.
+async fn get_conditional(octocrab: &Octocrab, url: &Url, cache: Option<&HttpCache>) -> Result<(String, Option<Url>)> {
+    let cached = cache.and_then(|cache| cache.get(url));
+    let mut request = octocrab.request_builder(url.clone(), Method::GET);
+    if let Some(cached) = &cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = send(request, url).await?;
+    if let (StatusCode::NOT_MODIFIED, Some(cached)) = (response.status(), cached) {
+        let next = cached.next.as_deref().and_then(|next| Url::parse(next).ok());
+        return Ok((cached.body, next));
+    }
+
+    let etag = header_string(response.headers(), ETAG.as_str());
+    let last_modified = header_string(response.headers(), LAST_MODIFIED.as_str());
+    let next = next_page_url(response.headers());
+    let body = response.text().await.map_err(|source| Error::Transport { url: url.to_string(), source })?;
+
+    if let Some(cache) = cache {
+        if etag.is_some() || last_modified.is_some() {
+            let response = CachedResponse {
+                url: url.to_string(),
+                etag,
+                last_modified,
+                next: next.as_ref().map(Url::to_string),
+                body: body.clone(),
+            };
+            cache.put(url, &response)?;
+        }
+    }
+
+    Ok((body, next))
+}
+
+/// Web page of a label, with its name percent-encoded.
+pub fn label_url(organization: &str, repo_name: &str, name: &str) -> String {
+    let mut url = Url::parse("https://github.com").expect("valid base URL");
+    url.path_segments_mut().expect("base URL has a path").extend([organization, repo_name, "labels", name]);
+    url.to_string()
+}
+
+/// Web page of a milestone, as returned by the API or built from its number.
+pub fn milestone_url(organization: &str, repo_name: &str, milestone: &Milestone) -> String {
+    milestone.html_url.clone().unwrap_or_else(|| format!("https://github.com/{}/{}/milestone/{}", organization, repo_name, milestone.number))
+}
+
+pub fn absolute_url(octocrab: &Octocrab, route: &str) -> Result<Url> {
+    octocrab.absolute_url(route).map_err(|error| Error::Config(format!("Invalid API route {}: {}", route, error)))
+}
+
+/// Sends a request and turns any non-success status into a typed error.
+///
+/// Hitting a rate limit waits until it resets (primary limit) or for `retry-after`
+/// (secondary limit) and sends the request again; GitHub did not act on it. Server
+/// errors and failed connections are only retried for idempotent methods, with jittered
+/// exponential backoff. Requests with a streamed body cannot be resent and are tried once.
+pub async fn send(request: reqwest::RequestBuilder, url: &Url) -> Result<Response> {
+    let (client, request) = request.build_split();
+    let mut request = request.map_err(|source| Error::Transport { url: url.to_string(), source })?;
+    let idempotent = matches!(*request.method(), Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS);
+
+    let mut attempt = 1;
+    loop {
+        // The original is kept for the next attempt while a copy is sent.
+        let (sent, next) = match request.try_clone() {
+            Some(copy) if attempt < MAX_ATTEMPTS => (copy, Some(request)),
+            _ => (request, None),
+        };
+        let result = {
+            let _permit = limits::acquire(url.as_str()).await;
+            client.execute(sent).await
+        };
+        let result = match result {
+            Ok(response) => check_status(response, url).await,
+            Err(source) => Err(Error::Transport { url: url.to_string(), source }),
+        };
+        if let Err(Error::RateLimited { .. } | Error::SecondaryRateLimited { .. } | Error::Http { status: 429, .. }) = &result {
+            limits::record_throttle(url.as_str());
+        }
+        let (error, next) = match (result, next) {
+            (Err(error), Some(next)) => (error, next),
+            (result, _) => return result,
+        };
+
+        let delay = match &error {
+            Error::RateLimited { reset, .. } => until_reset(*reset),
+            Error::SecondaryRateLimited { retry_after: Some(seconds), .. } => Duration::from_secs(*seconds),
+            Error::SecondaryRateLimited { retry_after: None, .. } => backoff(SECONDARY_RATE_LIMIT_DELAY, attempt),
+            Error::Http { status, .. } if idempotent && *status >= 500 => backoff(SERVER_ERROR_DELAY, attempt),
+            Error::Transport { .. } if idempotent => backoff(SERVER_ERROR_DELAY, attempt),
+            _ => return Err(error),
+        };
+
+        eprintln!("{}. Retrying in {} seconds (attempt {} of {})...", error, delay.as_secs(), attempt + 1, MAX_ATTEMPTS);
+        tokio::time::sleep(delay).await;
+        request = next;
+        attempt += 1;
+    }
+}
+
+/// Time left until the unix time `reset`, plus a second for clock skew.
+fn until_reset(reset: u64) -> Duration {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    Duration::from_secs(reset.saturating_sub(now) + 1)
+}
+
+/// `base` doubled for every earlier attempt, plus up to half of that again at random, so
+/// that concurrent requests do not all retry at the same moment.
+fn backoff(base: Duration, attempt: u32) -> Duration {
+    let delay = base * 2u32.pow(attempt - 1);
+    let random = RandomState::new().build_hasher().finish();
+    delay + delay.mul_f64((random % 1000) as f64 / 2000.0)
+}
+
+/// The `id` of a created object as text, whether the API returns a number or a string.
+pub fn id_of(object: &serde_json::Value) -> String {
+    match &object["id"] {
+        serde_json::Value::String(id) => id.clone(),
+        id => id.to_string(),
+    }
+}
+
+pub async fn parse_json<T: DeserializeOwned>(response: Response, url: &Url) -> Result<T> {
+    let text = response.text().await.map_err(|source| Error::Transport { url: url.to_string(), source })?;
+    serde_json::from_str(&text).map_err(|source| Error::serialization(url.as_str(), source))
+}
+
+async fn check_status(response: Response, url: &Url) -> Result<Response> {
+    let status = response.status();
+    // 304 only comes back for conditional requests, which handle it themselves.
+    if status.is_success() || status == StatusCode::NOT_MODIFIED {
+        return Ok(response);
+    }
+
+    let headers = response.headers().clone();
+    let text = response.text().await.unwrap_or_default();
+    Err(status_error(status, &headers, error_message(&text), url))
+}
+
+/// The error for a failed response, told apart by its status, rate limit headers and
+/// message.
+fn status_error(status: StatusCode, headers: &HeaderMap, message: String, url: &Url) -> Error {
+    let rate_limit_exhausted = header_u64(headers, "x-ratelimit-remaining") == Some(0);
+    let reset = header_u64(headers, "x-ratelimit-reset").unwrap_or(0);
+    let retry_after = header_u64(headers, RETRY_AFTER.as_str());
+
+    // Secondary limits come with `retry-after` or, on 403, only with a message saying so.
+    let secondary = retry_after.is_some() || message.to_lowercase().contains("secondary rate limit");
+    match status {
+        StatusCode::UNAUTHORIZED => Error::Auth(message),
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS if secondary => {
+            Error::SecondaryRateLimited { url: url.to_string(), retry_after }
+        }
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS if rate_limit_exhausted => {
+            Error::RateLimited { url: url.to_string(), reset }
+        }
+        _ => Error::Http { status: status.as_u16(), url: url.to_string(), message },
+    }
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    header_string(headers, name)?.parse().ok()
+}
+
+fn header_string(headers: &HeaderMap, name: &str) -> Option<String> {
+    Some(headers.get(name)?.to_str().ok()?.to_string())
+}
+
+/// GitHub error bodies look like `{"message": "...", "documentation_url": "..."}`;
+/// anything else is passed through as text.
+fn error_message(text: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(text)
+        .ok()
+        .and_then(|body| body.get("message").and_then(|message| message.as_str()).map(str::to_owned))
+        .unwrap_or_else(|| text.to_string())
+}
+
+/// The `rel="next"` target of the `link` header, if there is a next page.
+fn next_page_url(headers: &HeaderMap) -> Option<Url> {
+    let link = headers.get("link")?.to_str().ok()?;
+    link.split(',').find_map(|part| {
+        let (target, params) = part.split_once(';')?;
+        if !params.split(';').any(|param| param.trim() == "rel=\"next\"") {
+            return None;
+        }
+        Url::parse(target.trim().trim_start_matches('<').trim_end_matches('>')).ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn classify(status: u16, pairs: &[(&'static str, &str)], body: &str) -> Error {
+        let url = Url::parse("https://api.github.com/orgs/o/repos").unwrap();
+        status_error(StatusCode::from_u16(status).unwrap(), &headers(pairs), error_message(body), &url)
+    }
+
+    #[test]
+    fn tells_rate_limits_from_other_errors() {
+        let exhausted = [("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", "1700000000")];
+        assert!(matches!(classify(403, &exhausted, "{}"), Error::RateLimited { reset: 1700000000, .. }));
+        assert!(matches!(classify(429, &exhausted, "{}"), Error::RateLimited { reset: 1700000000, .. }));
+
+        let secondary = r#"{"message": "You have exceeded a secondary rate limit."}"#;
+        assert!(matches!(classify(403, &[], secondary), Error::SecondaryRateLimited { retry_after: None, .. }));
+        assert!(matches!(classify(429, &[("retry-after", "30")], "{}"), Error::SecondaryRateLimited { retry_after: Some(30), .. }));
+        // `retry-after` wins over an exhausted primary limit.
+        assert!(matches!(classify(403, &[("retry-after", "5"), exhausted[0]], "{}"), Error::SecondaryRateLimited { .. }));
+
+        assert!(matches!(classify(401, &[], r#"{"message": "Bad credentials"}"#), Error::Auth(message) if message == "Bad credentials"));
+        assert!(matches!(classify(403, &[("x-ratelimit-remaining", "10")], "{}"), Error::Http { status: 403, .. }));
+        assert!(matches!(classify(429, &[], "{}"), Error::Http { status: 429, .. }));
+        assert!(matches!(classify(404, &[], "Not Found"), Error::Http { status: 404, message, .. } if message == "Not Found"));
+    }
+
+    #[test]
+    fn follows_the_next_link() {
+        let link = "<https://api.github.com/repositories/1/issues?page=3>; rel=\"last\", \
+                    <https://api.github.com/repositories/1/issues?page=2>; rel=\"next\"";
+        let next = next_page_url(&headers(&[("link", link)])).unwrap();
+        assert_eq!(next.as_str(), "https://api.github.com/repositories/1/issues?page=2");
+
+        let last_page = "<https://api.github.com/repositories/1/issues?page=1>; rel=\"first\"";
+        assert_eq!(next_page_url(&headers(&[("link", last_page)])), None);
+        assert_eq!(next_page_url(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn doubles_the_backoff_with_jitter() {
+        for attempt in 1..=4 {
+            let base = Duration::from_secs(2) * 2u32.pow(attempt - 1);
+            let delay = backoff(Duration::from_secs(2), attempt);
+            assert!(delay >= base && delay <= base.mul_f64(1.5), "attempt {}: {:?}", attempt, delay);
+        }
+    }
+}
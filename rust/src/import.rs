@@ -0,0 +1,115 @@
+//! GitHub's issue import API (`/repos/{owner}/{repo}/import/issues`). Unlike the issues
+//! API, it takes the original `created_at` and `closed_at` and creates the issue and
+//! all its comments in one go, so a failed import leaves nothing half done. Imports run
+//! in the background; the status is polled until GitHub reports the issue created.
+
+use std::time::{Duration, Instant};
+use octocrab::Octocrab;
+use reqwest::header::ACCEPT;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::error::{Error, Result};
+use crate::github;
+
+/// The import API is still a preview and has to be asked for.
+const IMPORT_MEDIA_TYPE: &str = "application/vnd.github.golden-comet-preview+json";
+/// First wait between status checks; doubled up to `MAX_POLL_DELAY`.
+const FIRST_POLL_DELAY: Duration = Duration::from_secs(1);
+const MAX_POLL_DELAY: Duration = Duration::from_secs(30);
+/// Imports usually finish within seconds; one still pending after this is reported.
+const POLL_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// The issue part of an import request. Imports take a single assignee.
+#[derive(Debug, Serialize)]
+pub struct ImportedIssue<'a> {
+    pub title: &'a str,
+    pub body: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closed_at: Option<&'a str>,
+    pub closed: bool,
+    pub labels: Vec<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub milestone: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assignee: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportedComment<'a> {
+    pub created_at: &'a str,
+    pub body: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportStatus {
+    /// `pending`, `imported` or `failed`.
+    status: String,
+    /// API URL of this import, to poll.
+    url: String,
+    /// API URL of the created issue, once imported.
+    #[serde(default)]
+    issue_url: Option<String>,
+    #[serde(default)]
+    errors: Vec<Value>,
+}
+
+/// Imports `issue` with `comments` into the repository and waits for it. Returns the
+/// number of the created issue.
+pub async fn import_issue(
+    octocrab: &Octocrab,
+    organization: &str,
+    repo_name: &str,
+    issue: &ImportedIssue<'_>,
+    comments: &[ImportedComment<'_>],
+) -> Result<u64> {
+    let route = format!("repos/{}/{}/import/issues", organization, repo_name);
+    let body = json!({ "issue": issue, "comments": comments });
+    let mut status: ImportStatus = request(octocrab, Method::POST, &route, Some(&body)).await?;
+
+    let started = Instant::now();
+    let mut delay = FIRST_POLL_DELAY;
+    loop {
+        match status.status.as_str() {
+            "imported" => return issue_number(&status),
+            "failed" => {
+                let errors: Vec<String> = status.errors.iter().map(Value::to_string).collect();
+                return Err(Error::Import { url: status.url, message: errors.join("; ") });
+            }
+            _ if started.elapsed() > POLL_TIMEOUT => {
+                let message = format!("still {} after {} seconds", status.status, POLL_TIMEOUT.as_secs());
+                return Err(Error::Import { url: status.url, message });
+            }
+            _ => {}
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_POLL_DELAY);
+        status = request(octocrab, Method::GET, &status.url, None).await?;
+    }
+}
+
+/// The number at the end of `issue_url`.
+fn issue_number(status: &ImportStatus) -> Result<u64> {
+    status
+        .issue_url
+        .as_deref()
+        .and_then(|url| url.rsplit('/').next())
+        .and_then(|number| number.parse().ok())
+        .ok_or_else(|| Error::Import { url: status.url.clone(), message: "imported, but no issue URL was returned".to_string() })
+}
+
+/// `route` may also be an absolute API URL.
+async fn request(octocrab: &Octocrab, method: Method, route: &str, body: Option<&Value>) -> Result<ImportStatus> {
+    let url = github::absolute_url(octocrab, route)?;
+    let mut request = octocrab.request_builder(url.clone(), method).header(ACCEPT, IMPORT_MEDIA_TYPE);
+    if let Some(body) = body {
+        request = request.json(body);
+    }
+    let response = github::send(request, &url).await?;
+    github::parse_json(response, &url).await
+}
@@ -0,0 +1,40 @@
+//! Per-host limits on concurrent requests, shared by every GitHub, GitFlic and git
+//! request of the process, so that fetching several repositories at once does not
+//! open more connections to one host than it tolerates, and per-host counts of rate
+//! limit responses, which pacing slows down on.
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, OnceLock};
+use reqwest::Url;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+static PER_HOST: OnceLock<usize> = OnceLock::new();
+static HOSTS: LazyLock<Mutex<HashMap<String, Arc<Semaphore>>>> = LazyLock::new(Default::default);
+static THROTTLES: LazyLock<Mutex<HashMap<String, u64>>> = LazyLock::new(Default::default);
+
+/// Sets the limit once, before the first request; without it requests are not limited.
+pub fn set_per_host(limit: usize) {
+    let _ = PER_HOST.set(limit);
+}
+
+/// Waits until fewer than the limit of requests to the host of `url` are running and
+/// returns the permit to hold for the duration of the request. Local paths have no host
+/// and are never limited.
+pub async fn acquire(url: &str) -> Option<OwnedSemaphorePermit> {
+    let limit = *PER_HOST.get()?;
+    let host = Url::parse(url).ok()?.host_str()?.to_string();
+    let semaphore = HOSTS.lock().expect("host limits poisoned").entry(host).or_insert_with(|| Arc::new(Semaphore::new(limit))).clone();
+    semaphore.acquire_owned().await.ok()
+}
+
+/// Counts a rate limit response from the host of `url`.
+pub fn record_throttle(url: &str) {
+    if let Some(host) = Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)) {
+        *THROTTLES.lock().expect("throttle counts poisoned").entry(host).or_default() += 1;
+    }
+}
+
+/// Rate limit responses from `host` so far.
+pub fn throttles(host: &str) -> u64 {
+    THROTTLES.lock().expect("throttle counts poisoned").get(host).copied().unwrap_or(0)
+}
@@ -1,78 +1,97 @@
-use std::env;
-use std::fs;
-use std::path::Path;
+mod attribution;
+mod cache;
+mod cli;
+mod config;
+mod data;
+mod error;
+mod export;
+mod fetch;
+#[cfg(test)]
+mod fixtures;
+mod git;
+mod gitflic;
+mod github;
+mod import;
+mod limits;
+mod marker;
+mod models;
+mod pacing;
+mod push;
+mod releases;
+mod remove;
+mod settings;
+mod state;
+mod target;
+mod verify;
+
+use std::process::ExitCode;
+use clap::Parser;
 use dotenv::dotenv;
-use octocrab::{Octocrab, models};
-use serde_json::to_string_pretty;
+
+use cli::{Backend, Cli, Command, PushStage};
+use config::Config;
+use error::{Failures, Result};
+use target::Target;
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     dotenv().ok();
-
-    let GITHUB_ACCESS_TOKEN = env::var("GITHUB_ACCESS_TOKEN").expect("GITHUB_ACCESS_TOKEN must be set in .env file.");
-    let organization = env::var("ORGANIZATION").expect("ORGANIZATION must be set in .env file.");
-
-    let octocrab = Octocrab::builder().personal_token(GITHUB_ACCESS_TOKEN).build().unwrap();
-
-    let output_dir = format!("{}/data/{}", env::current_dir().unwrap().display(), &organization);
-    fs::create_dir_all(&output_dir).expect("Failed to create output directory.");
-
-    let repo_names = fetch_repositories(&octocrab, &organization, &output_dir).await;
-
-    for repo_name in repo_names {
-        fetch_issues(&octocrab, &organization, &repo_name, &output_dir).await;
-    }
-
-    println!("Data fetching completed. All data is stored in the {} directory.", output_dir);
-}
-
-async fn fetch_repositories(octocrab: &Octocrab, organization: &str, output_dir: &str) -> Vec<String> {
-    let mut page = octocrab
-        .orgs(organization)
-        .list_repos()
-        .per_page(100)
-        .send()
-        .await
-        .expect("Failed to fetch repositories.");
-
-    let mut repos: Vec<models::Repository> = Vec::new();
-
-    loop {
-        repos.extend(page.take_items());
-        if let Some(next_page) = page.next {
-            page = octocrab.get_page(&next_page).await.expect("Failed to fetch next page of repositories.");
-        } else {
-            break;
+    let cli = Cli::parse();
+
+    match run(&cli).await {
+        Ok(failures) if failures.is_empty() => ExitCode::SUCCESS,
+        Ok(failures) => {
+            eprintln!("{} repositories failed:", failures.len());
+            for (repo_name, error) in &failures {
+                eprintln!("  {}: {}", repo_name, error);
+            }
+            ExitCode::FAILURE
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
         }
     }
-
-    let repo_names: Vec<String> = repos.iter().map(|repo| repo.name.clone()).collect();
-    let repos_json = to_string_pretty(&repos).expect("Failed to serialize repositories.");
-    fs::write(format!("{}/orgrepos.json", output_dir), repos_json).expect("Failed to write orgrepos.json.");
-
-    repo_names
 }
 
-async fn fetch_issues(octocrab: &Octocrab, organization: &str, repo_name: &str, output_dir: &str) {
-    let mut page = octocrab
-        .issues(organization, repo_name)
-        .list()
-        .per_page(100)
-        .send()
-        .await
-        .expect("Failed to fetch issues.");
-
-    let mut issues: Vec<models::issues::Issue> = Vec::new();
+async fn run(cli: &Cli) -> Result<Failures> {
+    let config = Config::load(cli)?;
+    let source_data = config.source_data()?;
 
-    loop {
-        issues.extend(page.take_items());
-        if let Some(next_page) = page.next {
-            page = octocrab.get_page(&next_page).await.expect("Failed to fetch next page of issues.");
-        } else {
-            break;
+    match &cli.command {
+        Command::Fetch(_) => {
+            let octocrab = github::client(config.github_access_token()?)?;
+            limits::set_per_host(config.fetch.per_host);
+            fetch::run(&octocrab, config.github_access_token()?, config.source_organization()?, &config.fetch, &source_data).await
+        }
+        Command::Push { stage } => {
+            let target = Target::new(&config)?;
+            let state = source_data.state(config.backend.name(), config.target_organization()?)?;
+            match stage {
+                PushStage::Repos => push::repos(&target, config.target_organization()?, &source_data, &state).await,
+                PushStage::Issues { .. } => push::issues(&target, config.target_organization()?, &config.push, &source_data, &state).await,
+                PushStage::Settings => push::settings(&target, config.target_organization()?, &source_data).await,
+                PushStage::Code { .. } => {
+                    let auth = match config.backend {
+                        Backend::Github => git::Auth::github(config.github_access_token()?),
+                        Backend::Gitflic => git::Auth::gitflic(config.gitflic_username()?, config.gitflic_access_token()?),
+                    };
+                    push::code(&target, config.target_organization()?, &auth, &config.push, &source_data).await
+                }
+            }
+        }
+        Command::Verify => {
+            let target = Target::new(&config)?;
+            verify::run(&target, config.target_organization()?, &source_data).await
+        }
+        Command::Export(args) => {
+            let state = source_data.state(config.backend.name(), config.target_organization()?)?;
+            export::run(&state, args.format, args.output.as_deref())
+        }
+        Command::Remove { yes } => {
+            let target = Target::new(&config)?;
+            let state = source_data.state(config.backend.name(), config.target_organization()?)?;
+            remove::run(&target, config.source_organization()?, config.target_organization()?, &source_data, &state, *yes).await
         }
     }
-
-    let issues_json = to_string_pretty(&issues).expect("Failed to serialize issues.");
-    fs::write(format!("{}/{}.issues.json", output_dir, repo_name), issues_json).expect("Failed to write issues json.");
-}
\ No newline at end of file
+}
@@ -0,0 +1,41 @@
+//! Hidden markers naming the source of a migrated item. GitHub does not render HTML
+//! comments, so the marker is invisible, and it survives edits of the visible text.
+//! Looking items up by their marker makes reruns exact: edited, closed and identical
+//! items are all told apart.
+
+const PREFIX: &str = "<!-- gh-org-migrator source: ";
+const SUFFIX: &str = " -->";
+
+/// `body` with the marker of `source_id` appended on its own line.
+pub fn with_marker(body: &str, source_id: &str) -> String {
+    format!("{}\n\n{}{}{}", body, PREFIX, source_id, SUFFIX)
+}
+
+/// The source id in the last marker of `body`, if it has one.
+pub fn source_id(body: &str) -> Option<&str> {
+    let start = body.rfind(PREFIX)? + PREFIX.len();
+    let length = body[start..].find(SUFFIX)?;
+    Some(body[start..start + length].trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_the_marker() {
+        let body = with_marker("Text", "I_kwDO123");
+        assert_eq!(body, "Text\n\n<!-- gh-org-migrator source: I_kwDO123 -->");
+        assert_eq!(source_id(&body), Some("I_kwDO123"));
+        assert_eq!(source_id(&with_marker("", "I_1")), Some("I_1"));
+        assert_eq!(source_id("Text"), None);
+        assert_eq!(source_id("<!-- gh-org-migrator source: I_1"), None);
+    }
+
+    #[test]
+    fn the_last_marker_wins() {
+        // A body that quotes another migrated issue keeps its own marker at the end.
+        let quoted = with_marker("> quoted", "I_1");
+        assert_eq!(source_id(&with_marker(&quoted, "I_2")), Some("I_2"));
+    }
+}
@@ -0,0 +1,293 @@
+//! GitHub REST objects as stored in the data directory. Only the fields the migrator
+//! reads are typed; everything else is kept in `rest` so that files written by the
+//! JS tooling and by us round-trip without losing data.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Repository {
+    pub name: String,
+    #[serde(default)]
+    pub private: bool,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub homepage: Option<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub has_issues: Option<bool>,
+    #[serde(default)]
+    pub has_projects: Option<bool>,
+    #[serde(default)]
+    pub has_wiki: Option<bool>,
+    #[serde(default)]
+    pub has_downloads: Option<bool>,
+    #[serde(default)]
+    pub default_branch: Option<String>,
+    #[serde(default)]
+    pub clone_url: Option<String>,
+    /// `public`, `private` or `internal`; takes precedence over `private`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topics: Option<Vec<String>>,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_template: Option<bool>,
+    /// The merge settings are only returned by `GET /repos/{owner}/{repo}`, and only to
+    /// admins of the repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_merge_commit: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_squash_merge: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_rebase_merge: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_auto_merge: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delete_branch_on_merge: Option<bool>,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Issue {
+    pub number: u64,
+    /// Absent from exports of the first Rust and Python fetchers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    pub title: String,
+    #[serde(default)]
+    pub body: Option<String>,
+    pub html_url: String,
+    pub state: String,
+    /// `completed`, `not_planned` or `reopened`; absent from old exports.
+    #[serde(default)]
+    pub state_reason: Option<String>,
+    #[serde(default)]
+    pub closed_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    #[serde(default)]
+    pub milestone: Option<Milestone>,
+    #[serde(default)]
+    pub assignees: Vec<User>,
+    /// Present when the item returned by the issues endpoint is actually a pull request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_request: Option<Value>,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
+
+impl Issue {
+    pub fn is_pull_request(&self) -> bool {
+        self.pull_request.is_some()
+    }
+
+    /// What the migrated copy's marker names: the node id, which never changes, or the
+    /// URL for exports written without node ids.
+    pub fn source_id(&self) -> &str {
+        self.node_id.as_deref().unwrap_or(&self.html_url)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub login: String,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Label {
+    pub name: String,
+    /// Hex color without the leading `#`.
+    pub color: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Milestone {
+    pub number: u64,
+    pub title: String,
+    pub state: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub due_on: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html_url: Option<String>,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Release {
+    pub id: u64,
+    pub tag_name: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub body: Option<String>,
+    pub draft: bool,
+    pub prerelease: bool,
+    #[serde(default)]
+    pub author: Option<Value>,
+    #[serde(default)]
+    pub assets: Vec<ReleaseAsset>,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReleaseAsset {
+    pub id: u64,
+    pub name: String,
+    pub size: u64,
+    /// API URL; serves the binary when asked for `application/octet-stream`, also for
+    /// private repositories.
+    pub url: String,
+    /// `sha256:<hex>` for assets uploaded since GitHub started computing digests.
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
+
+/// An issue or pull request conversation comment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: u64,
+    pub node_id: String,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub user: Option<Value>,
+    pub html_url: String,
+    pub issue_url: String,
+    pub created_at: String,
+    pub updated_at: String,
+    /// Counts per reaction, e.g. `{"+1": 2, "heart": 1, "total_count": 3, ...}`.
+    #[serde(default)]
+    pub reactions: Option<Value>,
+    /// `userContentEdits` from the GraphQL API, newest first; only fetched on request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edits: Option<Vec<Value>>,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
+
+impl Comment {
+    /// The issue or pull request number, taken from the trailing segment of `issue_url`.
+    pub fn issue_number(&self) -> Option<u64> {
+        self.issue_url.rsplit('/').next()?.parse().ok()
+    }
+}
+
+/// A pull request as returned by `GET /repos/{owner}/{repo}/pulls/{number}`, which
+/// unlike the list endpoints includes the merge state and `merged_by`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PullRequest {
+    pub number: u64,
+    pub title: String,
+    #[serde(default)]
+    pub body: Option<String>,
+    pub html_url: String,
+    pub state: String,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub merged: bool,
+    #[serde(default)]
+    pub merged_at: Option<String>,
+    #[serde(default)]
+    pub merged_by: Option<Value>,
+    #[serde(default)]
+    pub mergeable_state: Option<String>,
+    pub base: Branch,
+    pub head: Branch,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
+
+/// An approval, change request or plain review comment on a pull request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Review {
+    pub id: u64,
+    pub node_id: String,
+    #[serde(default)]
+    pub user: Option<Value>,
+    #[serde(default)]
+    pub body: Option<String>,
+    /// `APPROVED`, `CHANGES_REQUESTED`, `COMMENTED`, `DISMISSED` or `PENDING`.
+    pub state: String,
+    #[serde(default)]
+    pub submitted_at: Option<String>,
+    #[serde(default)]
+    pub commit_id: Option<String>,
+    pub html_url: String,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
+
+/// An inline comment on the diff of a pull request. Replies point at the first
+/// comment of their thread through `in_reply_to_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewComment {
+    pub id: u64,
+    pub node_id: String,
+    #[serde(default)]
+    pub pull_request_review_id: Option<u64>,
+    #[serde(default)]
+    pub in_reply_to_id: Option<u64>,
+    pub path: String,
+    #[serde(default)]
+    pub line: Option<u64>,
+    #[serde(default)]
+    pub original_line: Option<u64>,
+    #[serde(default)]
+    pub side: Option<String>,
+    pub diff_hunk: String,
+    pub body: String,
+    #[serde(default)]
+    pub user: Option<Value>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub html_url: String,
+    pub pull_request_url: String,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
+
+impl ReviewComment {
+    /// The pull request number, taken from the trailing segment of `pull_request_url`.
+    pub fn pull_number(&self) -> Option<u64> {
+        self.pull_request_url.rsplit('/').next()?.parse().ok()
+    }
+}
+
+/// Everything reviewers said on one pull request.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PullReviews {
+    pub reviews: Vec<Review>,
+    pub comments: Vec<ReviewComment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Branch {
+    #[serde(rename = "ref")]
+    pub name: String,
+    pub sha: String,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
@@ -0,0 +1,47 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::PacingOptions;
+use crate::limits;
+
+/// Pause between write requests to a target, starting at the minimum interval. It
+/// doubles whenever a request to the host hit a rate limit since the last pause, and
+/// shrinks back by a fifth after every pause without one.
+pub struct Pacer {
+    host: String,
+    min: Duration,
+    max: Duration,
+    state: Mutex<State>,
+}
+
+struct State {
+    interval: Duration,
+    throttles_seen: u64,
+}
+
+impl Pacer {
+    pub fn new(host: &str, options: &PacingOptions) -> Self {
+        Pacer {
+            host: host.to_string(),
+            min: options.min_interval,
+            max: options.max_interval,
+            state: Mutex::new(State { interval: options.min_interval, throttles_seen: limits::throttles(host) }),
+        }
+    }
+
+    pub async fn pause(&self) {
+        let interval = {
+            let mut state = self.state.lock().expect("pacer poisoned");
+            let throttles = limits::throttles(&self.host);
+            if throttles > state.throttles_seen {
+                state.throttles_seen = throttles;
+                state.interval = (state.interval * 2).max(Duration::from_secs(1)).clamp(self.min, self.max);
+                eprintln!("{} is throttling requests, pausing {} seconds between them.", self.host, state.interval.as_secs());
+            } else {
+                state.interval = state.interval.mul_f64(0.8).max(self.min);
+            }
+            state.interval
+        };
+        tokio::time::sleep(interval).await;
+    }
+}
@@ -0,0 +1,660 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::future::Future;
+use octocrab::Octocrab;
+use serde_json::json;
+
+use crate::attribution;
+use crate::config::PushOptions;
+use crate::data::DataDir;
+use crate::error::{Error, Failures, Result};
+use crate::git::{self, Auth, PushReport};
+use crate::github;
+use crate::import::{self, ImportedComment, ImportedIssue};
+use crate::marker;
+use crate::models::{Comment, Issue, Label, Milestone, Repository};
+use crate::state::{Created, Entity, StateStore};
+use crate::target::Target;
+
+const SOURCE_LINK_PREFIX: &str = "Forked from ";
+const SOURCE_LINK_SUFFIX: &str = " by https://github.com/konard/gh-org-migrator";
+
+/// Creates every fetched repository that does not exist in the target organization yet,
+/// with as many of its settings as the target takes on creation.
+pub async fn repos(target: &Target, target_organization: &str, data: &DataDir, state: &StateStore) -> Result<Failures> {
+    let repos = data.read_repositories()?;
+
+    let mut failures = Vec::new();
+    let mut unapplied = Vec::new();
+    for repo in &repos {
+        match push_repository(target, target_organization, data.organization(), repo, state).await {
+            Ok(settings) => report_unapplied(&repo.name, settings, &mut unapplied),
+            Err(error) => {
+                eprintln!("Skipping repository {}: {}", repo.name, error);
+                failures.push((repo.name.clone(), error));
+            }
+        }
+    }
+    print_unapplied(&unapplied);
+
+    println!(
+        "Repositories creation completed. All repositories are created in the {} organization on {}.",
+        target_organization,
+        target.name()
+    );
+
+    Ok(failures)
+}
+
+async fn push_repository(
+    target: &Target,
+    target_organization: &str,
+    source_organization: &str,
+    repo: &Repository,
+    state: &StateStore,
+) -> Result<Vec<String>> {
+    let entity = Entity::repository(source_organization, &repo.name);
+    if state.is_created(&entity)? {
+        println!("Repository {} was created on {} by an earlier run. Skipping creation.", repo.name, target.name());
+        return Ok(Vec::new());
+    }
+    // Also catches a creation that went through but was not recorded before a crash, and
+    // repositories created by the JavaScript scripts. They are recorded with the id the
+    // target gave them, like the repositories created here.
+    if let Some(id) = target.repository_id(target_organization, &repo.name).await? {
+        println!("Repository {} already exists on {}. Skipping creation.", repo.name, target.name());
+        state.set_created(&entity, &Created { id, url: target.web_url(target_organization, &repo.name) })?;
+        return Ok(Vec::new());
+    }
+
+    println!("Creating repository {} in organization {} on {}...", repo.name, target_organization, target.name());
+    let mut unapplied = Vec::new();
+    let create = async {
+        let created = target.create_repository(target_organization, repo).await?;
+        unapplied = created.unapplied;
+        Ok(Created { id: created.id, url: target.web_url(target_organization, &repo.name) })
+    };
+    create_recorded(state, &entity, create).await?;
+    println!("Repository {} in organization {} on {} is created.", repo.name, target_organization, target.name());
+    target.pause().await;
+
+    Ok(unapplied)
+}
+
+/// Applies the settings of every fetched repository to the existing target repository,
+/// including the default branch and, last, the archived state. Meant to run after code
+/// and issues have been pushed, since an archived repository is read-only.
+pub async fn settings(target: &Target, target_organization: &str, data: &DataDir) -> Result<Failures> {
+    target.github()?;
+    let repos = data.read_repositories()?;
+
+    let mut failures = Vec::new();
+    let mut unapplied = Vec::new();
+    for repo in &repos {
+        println!("Applying settings of repository {}...", repo.name);
+        match target.apply_settings(target_organization, repo).await {
+            Ok(settings) => {
+                report_unapplied(&repo.name, settings, &mut unapplied);
+                target.pause().await;
+            }
+            Err(error) => {
+                eprintln!("Skipping settings of repository {}: {}", repo.name, error);
+                failures.push((repo.name.clone(), error));
+            }
+        }
+    }
+    print_unapplied(&unapplied);
+
+    println!("Settings applying completed for the {} organization on {}.", target_organization, target.name());
+
+    Ok(failures)
+}
+
+fn report_unapplied(repo_name: &str, settings: Vec<String>, unapplied: &mut Vec<(String, Vec<String>)>) {
+    if !settings.is_empty() {
+        eprintln!("Repository {}: could not apply {}.", repo_name, settings.join(", "));
+        unapplied.push((repo_name.to_string(), settings));
+    }
+}
+
+fn print_unapplied(unapplied: &[(String, Vec<String>)]) {
+    if unapplied.is_empty() {
+        return;
+    }
+    eprintln!("Settings that could not be applied to {} repositories:", unapplied.len());
+    for (repo_name, settings) in unapplied {
+        eprintln!("  {}: {}", repo_name, settings.join(", "));
+    }
+}
+
+/// Creates every fetched issue that an earlier run did not create and that has no copy in
+/// the target repository, open or closed. Copies are recognized by the hidden marker with
+/// the source issue's node id. The repository's labels and milestones are created first,
+/// so that issues keep their labels, milestone and assignees, and closed issues are closed
+/// with the same reason. With `import`, issues go through the issue import API instead,
+/// together with their comments and original timestamps. Otherwise the comments are
+/// replayed in order afterwards, each under a header naming its original author. Only
+/// GitHub targets have issues.
+pub async fn issues(target: &Target, target_organization: &str, options: &PushOptions, data: &DataDir, state: &StateStore) -> Result<Failures> {
+    let octocrab = target.github()?;
+    let repos = data.read_repositories()?;
+
+    let mut failures = Vec::new();
+    for repo in &repos {
+        let push = IssuePush {
+            target,
+            octocrab,
+            source_organization: data.organization(),
+            target_organization,
+            repo_name: &repo.name,
+            users: &options.users,
+            import: options.import,
+            comment_header: &options.comment_header,
+            state,
+        };
+        match push.run(data).await {
+            Ok(issue_failures) => failures.extend(issue_failures),
+            Err(error) => {
+                eprintln!("Skipping issues of repository {}: {}", repo.name, error);
+                failures.push((repo.name.clone(), error));
+            }
+        }
+    }
+
+    println!("Issues pushing completed. All issues are uploaded to the {} organization.", target_organization);
+
+    Ok(failures)
+}
+
+/// The issues of one repository on their way to the target.
+struct IssuePush<'a> {
+    target: &'a Target,
+    octocrab: &'a Octocrab,
+    source_organization: &'a str,
+    target_organization: &'a str,
+    repo_name: &'a str,
+    users: &'a BTreeMap<String, String>,
+    import: bool,
+    comment_header: &'a str,
+    state: &'a StateStore,
+}
+
+impl IssuePush<'_> {
+    /// Fails as a whole if the labels, milestones or existing issues cannot be read or
+    /// created; failed issues and comments are recorded and returned, and the rest go on.
+    async fn run(&self, data: &DataDir) -> Result<Failures> {
+        let issues = data.read_issues(self.repo_name)?;
+        let mut remaining = Vec::new();
+        for issue in &issues {
+            if !self.state.is_created(&Entity::issue(self.repo_name, issue))? {
+                remaining.push(issue);
+            }
+        }
+        if remaining.len() < issues.len() {
+            println!(
+                "{} issues of repository {} were created by an earlier run. Skipping them.",
+                issues.len() - remaining.len(),
+                self.repo_name
+            );
+        }
+        let comments = data.read_comments(self.repo_name)?.unwrap_or_default();
+        let mut failures = Vec::new();
+        if !remaining.is_empty() {
+            failures = self.push_issues(data, &issues, &remaining, &comments).await?;
+        }
+        failures.extend(self.push_comments(&issues, &comments).await?);
+        Ok(failures)
+    }
+
+    async fn push_issues(
+        &self,
+        data: &DataDir,
+        issues: &[Issue],
+        remaining: &[&Issue],
+        comments: &BTreeMap<u64, Vec<Comment>>,
+    ) -> Result<Failures> {
+        // Data fetched before labels and milestones had files of their own only has those
+        // attached to issues.
+        let labels = match data.read_labels(self.repo_name)? {
+            Some(labels) => labels,
+            None => unique_by(issues.iter().flat_map(|issue| issue.labels.iter().cloned()), |label| label.name.to_lowercase()),
+        };
+        let milestones = match data.read_milestones(self.repo_name)? {
+            Some(milestones) => milestones,
+            None => unique_by(issues.iter().filter_map(|issue| issue.milestone.clone()), |milestone| milestone.number),
+        };
+        self.push_labels(&labels).await?;
+        let milestone_numbers = self.push_milestones(&milestones).await?;
+        let assignees = self.assignable_users(remaining).await?;
+
+        // Issues without a record, or left pending by a crash, are looked up by their marker.
+        let existing: Vec<Issue> = github::get_all_pages(self.octocrab, &self.route("issues?state=all&per_page=100"), None).await?;
+        let existing = migrated_issues(&existing);
+
+        let mut failures = Vec::new();
+        for &issue in remaining {
+            let entity = Entity::issue(self.repo_name, issue);
+            let found = existing.get(issue.source_id()).or_else(|| existing.get(issue.html_url.as_str()));
+            if let Some(found) = found {
+                println!("Issue \"{}\" already exists in repository {}. Skipping creation.", issue.title, self.repo_name);
+                // A crash may have come between creating and closing it.
+                if issue.state == "closed" && found.state == "open" {
+                    self.close_issue(found.number, issue).await?;
+                }
+                self.state.set_created(&entity, &Created { id: found.number.to_string(), url: found.html_url.clone() })?;
+                continue;
+            }
+
+            println!("Creating issue \"{}\" in repository {}...", issue.title, self.repo_name);
+            let new_issue = NewIssue {
+                body: marker::with_marker(&body_with_source_link(issue), issue.source_id()),
+                labels: issue.labels.iter().map(|label| label.name.as_str()).collect(),
+                milestone: issue.milestone.as_ref().and_then(|milestone| milestone_numbers.get(&milestone.number).copied()),
+                assignees: issue.assignees.iter().filter_map(|user| assignees.get(&user.login).map(String::as_str)).collect(),
+            };
+            let create = async {
+                let number = if self.import {
+                    self.import_issue(issue, new_issue, comments.get(&issue.number).map_or(&[], Vec::as_slice)).await?
+                } else {
+                    self.create_issue(issue, new_issue).await?
+                };
+                let url = format!("https://github.com/{}/{}/issues/{}", self.target_organization, self.repo_name, number);
+                Ok(Created { id: number.to_string(), url })
+            };
+            // A failed issue is retried on the next run; it does not hold up the others.
+            match create_recorded(self.state, &entity, create).await {
+                Ok(_) => println!("Issue \"{}\" in repository {} is created.", issue.title, self.repo_name),
+                Err(error @ Error::State { .. }) => return Err(error),
+                Err(error) => {
+                    eprintln!("Skipping issue \"{}\" in repository {}: {}", issue.title, self.repo_name, error);
+                    failures.push((format!("{}#{}", self.repo_name, issue.number), error));
+                }
+            }
+            self.target.pause().await;
+        }
+
+        Ok(failures)
+    }
+
+    /// Creates the issue through the issues API and closes it if it is closed in the source.
+    async fn create_issue(&self, issue: &Issue, new_issue: NewIssue<'_>) -> Result<u64> {
+        let body = json!({
+            "title": issue.title,
+            "body": new_issue.body,
+            "labels": new_issue.labels,
+            "milestone": new_issue.milestone,
+            "assignees": new_issue.assignees,
+        });
+        let created: Issue = github::post(self.octocrab, &self.route("issues"), &body).await?;
+        if issue.state == "closed" {
+            self.close_issue(created.number, issue).await?;
+        }
+        Ok(created.number)
+    }
+
+    /// Imports the issue with its comments and timestamps. The import API takes a single
+    /// assignee and no `state_reason`; both are completed afterwards.
+    async fn import_issue(&self, issue: &Issue, new_issue: NewIssue<'_>, comments: &[Comment]) -> Result<u64> {
+        let closed = issue.state == "closed";
+        let imported = ImportedIssue {
+            title: &issue.title,
+            body: new_issue.body,
+            created_at: issue.created_at.as_deref(),
+            updated_at: issue.updated_at.as_deref(),
+            closed_at: issue.closed_at.as_deref().filter(|_| closed),
+            closed,
+            labels: new_issue.labels,
+            milestone: new_issue.milestone,
+            assignee: new_issue.assignees.first().copied(),
+        };
+        let comments: Vec<ImportedComment> = comments
+            .iter()
+            .map(|comment| ImportedComment {
+                created_at: &comment.created_at,
+                body: marker::with_marker(&attribution::with_header(self.comment_header, comment), &comment.node_id),
+            })
+            .collect();
+        let number = import::import_issue(self.octocrab, self.target_organization, self.repo_name, &imported, &comments).await?;
+
+        let others = new_issue.assignees.get(1..).unwrap_or_default();
+        if !others.is_empty() {
+            let route = self.route(&format!("issues/{}/assignees", number));
+            let _: serde_json::Value = github::post(self.octocrab, &route, &json!({ "assignees": others })).await?;
+        }
+        if closed && issue.state_reason.as_deref() == Some("not_planned") {
+            self.close_issue(number, issue).await?;
+        }
+        Ok(number)
+    }
+
+    /// Replays the comments of every created issue that an earlier run did not replay and
+    /// that have no copy on the target issue yet, recognized by their marker. Imported
+    /// issues brought theirs along and only get them recorded.
+    async fn push_comments(&self, issues: &[Issue], comments: &BTreeMap<u64, Vec<Comment>>) -> Result<Failures> {
+        let mut failures = Vec::new();
+        for issue in issues {
+            let Some(issue_comments) = comments.get(&issue.number) else {
+                continue;
+            };
+            // Issues that failed have nowhere to put their comments.
+            let Some(number) = self.state.target_id(&Entity::issue(self.repo_name, issue))? else {
+                continue;
+            };
+            let mut remaining = Vec::new();
+            for comment in issue_comments {
+                if !self.state.is_created(&Entity::comment(self.repo_name, comment))? {
+                    remaining.push(comment);
+                }
+            }
+            if remaining.is_empty() {
+                continue;
+            }
+            remaining.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+            let route = self.route(&format!("issues/{}/comments", number));
+            let existing: Vec<Comment> = github::get_all_pages(self.octocrab, &format!("{}?per_page=100", route), None).await?;
+            let existing: HashMap<&str, &Comment> = existing
+                .iter()
+                .filter_map(|found| Some((marker::source_id(found.body.as_deref()?)?, found)))
+                .collect();
+
+            println!("Replaying {} comments of issue \"{}\" in repository {}...", remaining.len(), issue.title, self.repo_name);
+            for comment in remaining {
+                let entity = Entity::comment(self.repo_name, comment);
+                if let Some(found) = existing.get(comment.node_id.as_str()) {
+                    self.state.set_created(&entity, &Created { id: found.id.to_string(), url: found.html_url.clone() })?;
+                    continue;
+                }
+                let body = json!({ "body": marker::with_marker(&attribution::with_header(self.comment_header, comment), &comment.node_id) });
+                let create = async {
+                    let created: Comment = github::post(self.octocrab, &route, &body).await?;
+                    Ok(Created { id: created.id.to_string(), url: created.html_url })
+                };
+                match create_recorded(self.state, &entity, create).await {
+                    Ok(_) => {}
+                    Err(error @ Error::State { .. }) => return Err(error),
+                    Err(error) => {
+                        eprintln!("Skipping comment {} of issue \"{}\" in repository {}: {}", comment.id, issue.title, self.repo_name, error);
+                        failures.push((format!("{}#{} comment {}", self.repo_name, issue.number, comment.id), error));
+                    }
+                }
+                self.target.pause().await;
+            }
+        }
+        Ok(failures)
+    }
+
+    /// Creates the labels the target repository does not have yet; GitHub compares label
+    /// names without case.
+    async fn push_labels(&self, labels: &[Label]) -> Result<()> {
+        let existing: Vec<Label> = github::get_all_pages(self.octocrab, &self.route("labels?per_page=100"), None).await?;
+        for label in labels {
+            let entity = Entity::label(self.source_organization, self.repo_name, label);
+            let url = github::label_url(self.target_organization, self.repo_name, &label.name);
+            if let Some(found) = existing.iter().find(|found| found.name.eq_ignore_ascii_case(&label.name)) {
+                self.state.set_created(&entity, &Created { id: found.name.clone(), url })?;
+                continue;
+            }
+
+            println!("Creating label \"{}\" in repository {}...", label.name, self.repo_name);
+            let body = json!({ "name": label.name, "color": label.color, "description": label.description });
+            let create = async {
+                let created: Label = github::post(self.octocrab, &self.route("labels"), &body).await?;
+                Ok(Created { id: created.name, url })
+            };
+            create_recorded(self.state, &entity, create).await?;
+            self.target.pause().await;
+        }
+        Ok(())
+    }
+
+    /// Creates the milestones the target repository does not have yet, matched by title,
+    /// which is unique within a repository. Returns the target number by source number.
+    async fn push_milestones(&self, milestones: &[Milestone]) -> Result<HashMap<u64, u64>> {
+        let existing: Vec<Milestone> = github::get_all_pages(self.octocrab, &self.route("milestones?state=all&per_page=100"), None).await?;
+        let mut numbers = HashMap::new();
+        for milestone in milestones {
+            let entity = Entity::milestone(self.source_organization, self.repo_name, milestone);
+            let created = match existing.iter().find(|found| found.title == milestone.title) {
+                Some(found) => {
+                    let created = self.milestone_created(found);
+                    self.state.set_created(&entity, &created)?;
+                    created
+                }
+                None => {
+                    println!("Creating milestone \"{}\" in repository {}...", milestone.title, self.repo_name);
+                    let body = json!({
+                        "title": milestone.title,
+                        "state": milestone.state,
+                        "description": milestone.description,
+                        "due_on": milestone.due_on,
+                    });
+                    let create = async {
+                        let created: Milestone = github::post(self.octocrab, &self.route("milestones"), &body).await?;
+                        Ok(self.milestone_created(&created))
+                    };
+                    let created = create_recorded(self.state, &entity, create).await?;
+                    self.target.pause().await;
+                    created
+                }
+            };
+            if let Ok(number) = created.id.parse() {
+                numbers.insert(milestone.number, number);
+            }
+        }
+        Ok(numbers)
+    }
+
+    fn milestone_created(&self, milestone: &Milestone) -> Created {
+        Created { id: milestone.number.to_string(), url: github::milestone_url(self.target_organization, self.repo_name, milestone) }
+    }
+
+    /// Target login by source login for everyone assigned to `issues` who can be assigned
+    /// in the target repository. Assigning anyone else would fail the issue.
+    async fn assignable_users(&self, issues: &[&Issue]) -> Result<HashMap<String, String>> {
+        let mut assignable = HashMap::new();
+        let logins: BTreeSet<&str> = issues.iter().flat_map(|issue| issue.assignees.iter().map(|user| user.login.as_str())).collect();
+        for login in logins {
+            let target_login = self.users.get(login).map_or(login, String::as_str);
+            if github::exists(self.octocrab, &self.route(&format!("assignees/{}", target_login))).await? {
+                assignable.insert(login.to_string(), target_login.to_string());
+            } else {
+                eprintln!("{} cannot be assigned in repository {}, leaving their issues unassigned.", target_login, self.repo_name);
+            }
+        }
+        Ok(assignable)
+    }
+
+    async fn close_issue(&self, number: u64, source: &Issue) -> Result<()> {
+        let mut body = json!({ "state": "closed" });
+        // `reopened` only describes open issues.
+        if let Some(reason @ ("completed" | "not_planned")) = source.state_reason.as_deref() {
+            body["state_reason"] = json!(reason);
+        }
+        let _: serde_json::Value = github::patch(self.octocrab, &self.route(&format!("issues/{}", number)), &body).await?;
+        Ok(())
+    }
+
+    /// `repos/<target org>/<repo>/<path>`
+    fn route(&self, path: &str) -> String {
+        format!("repos/{}/{}/{}", self.target_organization, self.repo_name, path)
+    }
+}
+
+/// What an issue is created with on the target.
+struct NewIssue<'a> {
+    /// The source body with its source link and marker.
+    body: String,
+    labels: Vec<&'a str>,
+    milestone: Option<u64>,
+    /// Target logins.
+    assignees: Vec<&'a str>,
+}
+
+/// The first item of every key, in order.
+fn unique_by<T, K: Eq + std::hash::Hash>(items: impl Iterator<Item = T>, key: impl Fn(&T) -> K) -> Vec<T> {
+    let mut seen = HashSet::new();
+    items.filter(|item| seen.insert(key(item))).collect()
+}
+
+/// Runs `create` between recording `entity` as pending and recording it as created or failed.
+async fn create_recorded(state: &StateStore, entity: &Entity<'_>, create: impl Future<Output = Result<Created>>) -> Result<Created> {
+    state.set_pending(entity)?;
+    match create.await {
+        Ok(created) => {
+            state.set_created(entity, &created)?;
+            Ok(created)
+        }
+        Err(error) => {
+            state.set_failed(entity, &error)?;
+            Err(error)
+        }
+    }
+}
+
+/// Target issues by the source they were migrated from: the id in their marker or, for
+/// issues created before markers, the URL in their source link.
+fn migrated_issues(issues: &[Issue]) -> HashMap<&str, &Issue> {
+    let mut migrated = HashMap::new();
+    for issue in issues.iter().filter(|issue| !issue.is_pull_request()) {
+        let body = issue.body.as_deref().unwrap_or("");
+        let linked = || Some(body.split_once(SOURCE_LINK_PREFIX)?.1.split_once(SOURCE_LINK_SUFFIX)?.0);
+        let source = marker::source_id(body).or_else(linked);
+        if let Some(source) = source {
+            migrated.entry(source).or_insert(issue);
+        }
+    }
+    migrated
+}
+
+fn body_with_source_link(issue: &Issue) -> String {
+    let source_link = format!("{}{}{}", SOURCE_LINK_PREFIX, issue.html_url, SOURCE_LINK_SUFFIX);
+    match issue.body.as_deref() {
+        Some(body) if !body.trim().is_empty() => format!("{}\n\n---\n{}", body, source_link),
+        _ => source_link,
+    }
+}
+
+/// Pushes the mirrored branches and tags of every fetched repository, one push per
+/// repository, and reports what happened to each ref.
+pub async fn code(target: &Target, target_organization: &str, auth: &Auth, options: &PushOptions, data: &DataDir) -> Result<Failures> {
+    let repos = data.read_repositories()?;
+
+    let mut failures = Vec::new();
+    for repo in &repos {
+        if let Err(error) = push_code(target, target_organization, auth, options, &repo.name, data).await {
+            eprintln!("Skipping code of repository {}: {}", repo.name, error);
+            failures.push((repo.name.clone(), error));
+        }
+    }
+
+    println!(
+        "Code pushing completed. All branches and tags are pushed to the {} organization on {}.",
+        target_organization,
+        target.name()
+    );
+
+    Ok(failures)
+}
+
+async fn push_code(
+    target: &Target,
+    target_organization: &str,
+    auth: &Auth,
+    options: &PushOptions,
+    repo_name: &str,
+    data: &DataDir,
+) -> Result<()> {
+    println!("Pushing repository {} to organization {} on {}...", repo_name, target_organization, target.name());
+    let url = target.git_url(target_organization, repo_name);
+    let report = git::push(&data.code_dir(repo_name), &url, Some(auth), &options.refspecs).await?;
+    print_push_report(repo_name, &report);
+
+    if report.changed() {
+        target.pause().await;
+    }
+
+    Ok(())
+}
+
+fn print_push_report(repo_name: &str, report: &PushReport) {
+    if !report.changed() {
+        println!("Repository {} is already up to date ({} refs unchanged).", repo_name, report.unchanged.len());
+        return;
+    }
+
+    println!(
+        "Repository {} pushed: {} created, {} updated, {} unchanged.",
+        repo_name,
+        report.created.len(),
+        report.updated.len(),
+        report.unchanged.len()
+    );
+    for (kind, names) in [("created", &report.created), ("updated", &report.updated)] {
+        for name in names {
+            println!("  {} {}", kind, name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::state::{Kind, Status};
+
+    fn issue_entity() -> Entity<'static> {
+        Entity { repo: "r", kind: Kind::Issue, source_id: "1".to_string(), source_url: "https://github.com/o/r/issues/1".to_string() }
+    }
+
+    fn target_issue(number: u64, body: String) -> Issue {
+        Issue { body: Some(body), ..fixtures::issue(number, "Copy", "2024-01-01T00:00:00Z") }
+    }
+
+    #[test]
+    fn finds_migrated_issues_by_marker_or_source_link() {
+        let source = fixtures::issue(1, "Bug", "2024-01-01T00:00:00Z");
+        let unmarked = Issue { node_id: None, html_url: "https://github.com/o/r/issues/2".to_string(), ..source.clone() };
+        let issues = [
+            target_issue(10, marker::with_marker(&body_with_source_link(&source), source.source_id())),
+            target_issue(11, body_with_source_link(&unmarked)),
+            target_issue(12, "Written by hand".to_string()),
+        ];
+
+        let migrated = migrated_issues(&issues);
+        assert_eq!(migrated.len(), 2);
+        assert_eq!(migrated["I_1"].number, 10);
+        assert_eq!(migrated["https://github.com/o/r/issues/2"].number, 11);
+    }
+
+    #[test]
+    fn ignores_pull_requests_and_keeps_the_first_copy() {
+        let marked = |number| target_issue(number, marker::with_marker("Body", "I_1"));
+        let pull_request = Issue { pull_request: Some(json!({ "url": "https://api.github.com/repos/o/r/pulls/9" })), ..marked(9) };
+        let issues = [pull_request, marked(10), marked(11)];
+
+        let migrated = migrated_issues(&issues);
+        assert_eq!(migrated.len(), 1);
+        assert_eq!(migrated["I_1"].number, 10);
+    }
+
+    #[tokio::test]
+    async fn records_the_outcome_of_a_creation() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = StateStore::open(&dir.path().join("state.sqlite"), "github", "link-foundation").unwrap();
+        let entity = issue_entity();
+
+        let failed = create_recorded(&state, &entity, async {
+            assert_eq!(state.status(&entity).unwrap(), Some(Status::Pending));
+            Err(Error::Config("boom".to_string()))
+        });
+        assert!(failed.await.is_err());
+        assert_eq!(state.status(&entity).unwrap(), Some(Status::Failed));
+
+        let created = create_recorded(&state, &entity, async {
+            assert_eq!(state.status(&entity).unwrap(), Some(Status::Pending));
+            Ok(Created { id: "7".to_string(), url: "https://github.com/link-foundation/r/issues/7".to_string() })
+        });
+        assert_eq!(created.await.unwrap().id, "7");
+        assert_eq!(state.target_id(&entity).unwrap().as_deref(), Some("7"));
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use octocrab::Octocrab;
+use reqwest::header::{ACCEPT, RANGE};
+use reqwest::{Method, StatusCode, Url};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::data::{self, DataDir};
+use crate::error::{Error, Result};
+use crate::github;
+use crate::models::{Release, ReleaseAsset};
+
+/// Per release directory record of the checksums of completely downloaded assets, used
+/// to verify assets GitHub has no `digest` for on later runs.
+const CHECKSUMS_FILE: &str = ".checksums.json";
+
+/// Saves the release metadata of the repository and, if asked to, downloads every asset
+/// to `<repo>.releases/<tag>/`.
+pub async fn fetch_releases(octocrab: &Octocrab, organization: &str, repo_name: &str, download_assets: bool, data: &DataDir) -> Result<()> {
+    println!("Fetching releases for repository {}...", repo_name);
+    let route = format!("repos/{}/{}/releases?per_page=100", organization, repo_name);
+    let releases: Vec<Release> = github::get_all_pages(octocrab, &route, Some(&data.http_cache())).await?;
+    data.write_releases(repo_name, &releases)?;
+
+    if download_assets {
+        for release in &releases {
+            let dir = data.release_dir(repo_name, &release.tag_name);
+            for asset in &release.assets {
+                download_asset(octocrab, asset, &dir).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Downloads an asset unless a verified copy already exists. The download goes to a
+/// `.part` file first, so an interrupted run resumes where it stopped.
+async fn download_asset(octocrab: &Octocrab, asset: &ReleaseAsset, dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).map_err(|source| Error::io(dir, source))?;
+    let file_name = data::safe_file_name(&asset.name);
+    let path = dir.join(&file_name);
+    let checksums_path = dir.join(CHECKSUMS_FILE);
+    let mut checksums: BTreeMap<String, String> =
+        if checksums_path.exists() { data::read_json(&checksums_path)? } else { BTreeMap::new() };
+
+    if path.exists() {
+        match verify(&path, asset, checksums.get(&asset.name)) {
+            Ok(_) => return Ok(()),
+            Err(error) => {
+                eprintln!("Downloading asset {} again: {}", asset.name, error);
+                fs::remove_file(&path).map_err(|source| Error::io(&path, source))?;
+            }
+        }
+    }
+
+    let partial = dir.join(format!("{}.part", file_name));
+    download_to(octocrab, asset, &partial).await?;
+    let checksum = match verify(&partial, asset, None) {
+        Ok(checksum) => checksum,
+        Err(error) => {
+            fs::remove_file(&partial).map_err(|source| Error::io(&partial, source))?;
+            return Err(error);
+        }
+    };
+    fs::rename(&partial, &path).map_err(|source| Error::io(&path, source))?;
+
+    checksums.insert(asset.name.clone(), checksum);
+    data::write_json(&checksums_path, &checksums)
+}
+
+async fn download_to(octocrab: &Octocrab, asset: &ReleaseAsset, partial: &Path) -> Result<()> {
+    let offset = fs::metadata(partial).map(|metadata| metadata.len()).unwrap_or(0);
+    if offset > 0 && offset >= asset.size {
+        return Ok(());
+    }
+
+    let url = Url::parse(&asset.url).map_err(|error| Error::Config(format!("Invalid asset URL {}: {}", asset.url, error)))?;
+    let mut request = octocrab.request_builder(url.clone(), Method::GET).header(ACCEPT, "application/octet-stream");
+    if offset > 0 {
+        println!("Resuming download of asset {} at {} of {} bytes...", asset.name, offset, asset.size);
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    } else {
+        println!("Downloading asset {} ({} bytes)...", asset.name, asset.size);
+    }
+    let mut response = github::send(request, &url).await?;
+
+    // A server that ignores the range answers 200 with the whole file.
+    let resumed = response.status() == StatusCode::PARTIAL_CONTENT;
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(partial)
+        .await
+        .map_err(|source| Error::io(partial, source))?;
+    while let Some(chunk) = response.chunk().await.map_err(|source| Error::Transport { url: url.to_string(), source })? {
+        file.write_all(&chunk).await.map_err(|source| Error::io(partial, source))?;
+    }
+    file.flush().await.map_err(|source| Error::io(partial, source))
+}
+
+/// Checks the size and, when GitHub or an earlier run recorded one, the SHA-256 of a
+/// downloaded file. Returns the checksum as `sha256:<hex>`.
+fn verify(path: &Path, asset: &ReleaseAsset, recorded: Option<&String>) -> Result<String> {
+    let size = fs::metadata(path).map_err(|source| Error::io(path, source))?.len();
+    if size != asset.size {
+        return Err(Error::Verification(format!("{} has {} bytes, expected {}", path.display(), size, asset.size)));
+    }
+
+    let checksum = sha256(path).map_err(|source| Error::io(path, source))?;
+    if let Some(expected) = asset.digest.as_ref().or(recorded) {
+        if !expected.eq_ignore_ascii_case(&checksum) {
+            return Err(Error::Verification(format!("{} has checksum {}, expected {}", path.display(), checksum, expected)));
+        }
+    }
+
+    Ok(checksum)
+}
+
+fn sha256(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("sha256:{}", hex::encode(hasher.finalize())))
+}
@@ -0,0 +1,60 @@
+use std::io::{self, BufRead, Write};
+
+use crate::data::DataDir;
+use crate::error::{Error, Failures, Result};
+use crate::state::StateStore;
+use crate::target::Target;
+
+/// Deletes the fetched repositories from the target organization after confirmation.
+pub async fn run(target: &Target, source_organization: &str, target_organization: &str, data: &DataDir, state: &StateStore, yes: bool) -> Result<Failures> {
+    target.github()?;
+    let repos = data.read_repositories()?;
+    let repo_names: Vec<&str> = repos.iter().map(|repo| repo.name.as_str()).collect();
+
+    println!("Source organization: {}", source_organization);
+    println!("Target organization: {}", target_organization);
+    println!("Repositories to delete from {} organization: {}", target_organization, repo_names.join(", "));
+
+    if !yes && !confirm("Do you want to proceed with the deletion of the listed repositories?")? {
+        println!("Operation cancelled by the user.");
+        return Ok(Vec::new());
+    }
+
+    let mut failures = Vec::new();
+    for repo_name in repo_names {
+        println!("Deleting repository {} from organization {}...", repo_name, target_organization);
+        let result = match target.delete_repository(target_organization, repo_name).await {
+            Ok(()) => {
+                println!("Repository {} deleted successfully.", repo_name);
+                Ok(())
+            }
+            Err(Error::Http { status: 404, .. }) => {
+                println!("Repository {} does not exist in organization {}. Skipping deletion.", repo_name, target_organization);
+                Ok(())
+            }
+            Err(error) => Err(error),
+        };
+        // A later push has to create the repository and its issues again.
+        if let Err(error) = result.and_then(|()| state.forget_repository(repo_name)) {
+            eprintln!("Error deleting repository {}: {}", repo_name, error);
+            failures.push((repo_name.to_string(), error));
+        }
+    }
+
+    println!(
+        "Repositories from {} have been deleted in the {} organization.",
+        source_organization, target_organization
+    );
+
+    Ok(failures)
+}
+
+fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush().map_err(|source| Error::io("stdout", source))?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).map_err(|source| Error::io("stdin", source))?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
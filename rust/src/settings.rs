@@ -0,0 +1,119 @@
+//! Repository settings copied from the source beyond the name, visibility, description,
+//! homepage and feature flags that `js/push-repositories.js` sends. GitHub ignores
+//! settings it does not allow for an organization instead of failing, so what it
+//! returns is compared with what was asked for, and the differences are reported.
+
+use octocrab::Octocrab;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::github;
+use crate::models::Repository;
+
+/// The settings of `repo` that GitHub takes on creation and on update. Settings the
+/// source did not report are left out rather than reset.
+pub fn github_settings(repo: &Repository) -> Map<String, Value> {
+    let mut settings = Map::new();
+    match &repo.visibility {
+        Some(visibility) => settings.insert("visibility".to_string(), json!(visibility)),
+        None => settings.insert("private".to_string(), json!(repo.private)),
+    };
+    for (name, value) in [("description", &repo.description), ("homepage", &repo.homepage)] {
+        if let Some(value) = value {
+            settings.insert(name.to_string(), json!(value));
+        }
+    }
+    let flags = [
+        ("has_issues", repo.has_issues),
+        ("has_projects", repo.has_projects),
+        ("has_wiki", repo.has_wiki),
+        ("has_downloads", repo.has_downloads),
+        ("is_template", repo.is_template),
+        ("allow_merge_commit", repo.allow_merge_commit),
+        ("allow_squash_merge", repo.allow_squash_merge),
+        ("allow_rebase_merge", repo.allow_rebase_merge),
+        ("allow_auto_merge", repo.allow_auto_merge),
+        ("delete_branch_on_merge", repo.delete_branch_on_merge),
+    ];
+    for (name, value) in flags {
+        if let Some(value) = value {
+            settings.insert(name.to_string(), Value::Bool(value));
+        }
+    }
+    settings
+}
+
+/// The settings of `wanted` that `actual`, the repository as the target returned it, does
+/// not have, as `name (wanted x, got y)`.
+pub fn unapplied(wanted: &Map<String, Value>, actual: &Value) -> Vec<String> {
+    wanted
+        .iter()
+        .filter(|(name, value)| !same(value, &actual[name.as_str()]))
+        .map(|(name, value)| format!("{} (wanted {}, got {})", name, value, actual[name.as_str()]))
+        .collect()
+}
+
+/// GitHub returns `null` for an empty description or homepage.
+fn same(wanted: &Value, actual: &Value) -> bool {
+    let blank = |value: &Value| value.is_null() || value.as_str() == Some("");
+    wanted == actual || (blank(wanted) && blank(actual))
+}
+
+/// Replaces the topics of the target repository with those of `repo`, returning the
+/// topics that did not stick. The topics endpoint fails on its own, so a failure is
+/// reported as an unapplied setting instead of failing the repository.
+pub async fn apply_topics(octocrab: &Octocrab, organization: &str, repo: &Repository) -> Vec<String> {
+    #[derive(Deserialize)]
+    struct Topics {
+        names: Vec<String>,
+    }
+
+    let Some(topics) = repo.topics.as_ref().filter(|topics| !topics.is_empty()) else {
+        return Vec::new();
+    };
+    let route = format!("repos/{}/{}/topics", organization, repo.name);
+    match github::put::<_, Topics>(octocrab, &route, &json!({ "names": topics })).await {
+        Ok(applied) => {
+            let missing: Vec<&str> = topics.iter().filter(|topic| !applied.names.contains(topic)).map(String::as_str).collect();
+            if missing.is_empty() {
+                Vec::new()
+            } else {
+                vec![format!("topics ({} not accepted)", missing.join(", "))]
+            }
+        }
+        Err(error) => vec![format!("topics ({})", error)],
+    }
+}
+
+/// The settings of `repo` that GitFlic projects have no equivalent for: everything but
+/// the visibility, description and language. Settings at their GitHub default are not
+/// worth reporting.
+pub fn unsupported_by_gitflic(repo: &Repository) -> Vec<String> {
+    let mut unsupported = Vec::new();
+    if repo.visibility.as_deref() == Some("internal") {
+        unsupported.push("visibility (internal)".to_string());
+    }
+    if repo.homepage.as_deref().is_some_and(|homepage| !homepage.is_empty()) {
+        unsupported.push("homepage".to_string());
+    }
+    if repo.topics.as_ref().is_some_and(|topics| !topics.is_empty()) {
+        unsupported.push("topics".to_string());
+    }
+    let flags = [
+        ("is_template", repo.is_template, false),
+        ("allow_merge_commit", repo.allow_merge_commit, true),
+        ("allow_squash_merge", repo.allow_squash_merge, true),
+        ("allow_rebase_merge", repo.allow_rebase_merge, true),
+        ("allow_auto_merge", repo.allow_auto_merge, false),
+        ("delete_branch_on_merge", repo.delete_branch_on_merge, false),
+    ];
+    for (name, value, default) in flags {
+        if value.is_some_and(|value| value != default) {
+            unsupported.push(name.to_string());
+        }
+    }
+    if repo.archived {
+        unsupported.push("archived".to_string());
+    }
+    unsupported
+}
@@ -0,0 +1,387 @@
+//! What `push` has already done to a target organization, in `state.sqlite` next to the
+//! fetched data. Every entity is recorded as pending right before it is created and as
+//! created, with its id and URL on the target, right after, so a rerun skips what is
+//! done and only has to look up entities a crash left pending. The created entities
+//! double as the source-to-target mapping that `export` writes out.
+
+use std::path::{Path, PathBuf};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::github;
+use crate::models::{Comment, Issue, Label, Milestone};
+
+/// Each entry upgrades the database by one version; `PRAGMA user_version` counts the
+/// entries already applied.
+const MIGRATIONS: [&str; 2] = [
+    "CREATE TABLE IF NOT EXISTS entities (
+        target TEXT NOT NULL,
+        repo TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        source_id TEXT NOT NULL,
+        status TEXT NOT NULL,
+        target_id TEXT,
+        error TEXT,
+        updated_at TEXT NOT NULL,
+        PRIMARY KEY (target, repo, kind, source_id)
+    );",
+    "ALTER TABLE entities ADD COLUMN source_url TEXT;
+     ALTER TABLE entities ADD COLUMN target_url TEXT;",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Repository,
+    Issue,
+    Label,
+    Milestone,
+    Comment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Creation started but was not confirmed; the entity may or may not exist.
+    Pending,
+    Created,
+    Failed,
+}
+
+/// A source entity, identified within its repository by `source_id`: the name of a
+/// repository or label, the number of an issue or milestone, or the id of a comment.
+pub struct Entity<'a> {
+    pub repo: &'a str,
+    pub kind: Kind,
+    pub source_id: String,
+    pub source_url: String,
+}
+
+/// Where an entity ended up on the target.
+pub struct Created {
+    pub id: String,
+    pub url: String,
+}
+
+/// One row of the source-to-target mapping. Rows recorded before URLs were kept have
+/// none.
+#[derive(Debug, Serialize)]
+pub struct Mapping {
+    pub kind: String,
+    pub repo: String,
+    pub source_id: String,
+    pub source_url: Option<String>,
+    pub target_id: Option<String>,
+    pub target_url: Option<String>,
+}
+
+/// The state of one target organization (`<backend>/<organization>`).
+pub struct StateStore {
+    path: PathBuf,
+    target: String,
+    connection: Connection,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Repository => "repository",
+            Kind::Issue => "issue",
+            Kind::Label => "label",
+            Kind::Milestone => "milestone",
+            Kind::Comment => "comment",
+        }
+    }
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Created => "created",
+            Status::Failed => "failed",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "pending" => Some(Status::Pending),
+            "created" => Some(Status::Created),
+            "failed" => Some(Status::Failed),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Entity<'a> {
+    /// Source repositories always live on GitHub.
+    pub fn repository(source_organization: &str, repo_name: &'a str) -> Self {
+        Entity {
+            repo: repo_name,
+            kind: Kind::Repository,
+            source_id: repo_name.to_string(),
+            source_url: format!("https://github.com/{}/{}", source_organization, repo_name),
+        }
+    }
+
+    pub fn issue(repo_name: &'a str, issue: &Issue) -> Self {
+        Entity { repo: repo_name, kind: Kind::Issue, source_id: issue.number.to_string(), source_url: issue.html_url.clone() }
+    }
+
+    pub fn comment(repo_name: &'a str, comment: &Comment) -> Self {
+        Entity { repo: repo_name, kind: Kind::Comment, source_id: comment.id.to_string(), source_url: comment.html_url.clone() }
+    }
+
+    pub fn label(source_organization: &str, repo_name: &'a str, label: &Label) -> Self {
+        Entity {
+            repo: repo_name,
+            kind: Kind::Label,
+            source_id: label.name.clone(),
+            source_url: github::label_url(source_organization, repo_name, &label.name),
+        }
+    }
+
+    pub fn milestone(source_organization: &str, repo_name: &'a str, milestone: &Milestone) -> Self {
+        Entity {
+            repo: repo_name,
+            kind: Kind::Milestone,
+            source_id: milestone.number.to_string(),
+            source_url: github::milestone_url(source_organization, repo_name, milestone),
+        }
+    }
+}
+
+impl StateStore {
+    pub fn open(path: &Path, backend: &str, organization: &str) -> Result<Self> {
+        let state_error = |source| Error::State { path: path.to_path_buf(), source };
+        let connection = Connection::open(path).map_err(state_error)?;
+
+        let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0)).map_err(state_error)?;
+        if version > MIGRATIONS.len() {
+            return Err(Error::Config(format!(
+                "{} was written by a newer version (schema {}, this version understands up to {}).",
+                path.display(),
+                version,
+                MIGRATIONS.len()
+            )));
+        }
+        for (applied, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            connection
+                .execute_batch(&format!("BEGIN; {} PRAGMA user_version = {}; COMMIT;", migration, applied + 1))
+                .map_err(state_error)?;
+        }
+
+        Ok(StateStore { path: path.to_path_buf(), target: format!("{}/{}", backend, organization), connection })
+    }
+
+    /// `None` if the entity was never pushed to this target.
+    pub fn status(&self, entity: &Entity) -> Result<Option<Status>> {
+        let status: Option<String> = self
+            .connection
+            .query_row(
+                "SELECT status FROM entities WHERE target = ?1 AND repo = ?2 AND kind = ?3 AND source_id = ?4",
+                params![self.target, entity.repo, entity.kind.name(), entity.source_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|source| self.error(source))?;
+
+        // An unknown status, written by a newer version, is treated like an interrupted creation.
+        Ok(status.map(|status| Status::parse(&status).unwrap_or(Status::Pending)))
+    }
+
+    /// Whether the entity is known to exist on the target.
+    pub fn is_created(&self, entity: &Entity) -> Result<bool> {
+        Ok(self.status(entity)? == Some(Status::Created))
+    }
+
+    /// The target id of the entity, if it was created.
+    pub fn target_id(&self, entity: &Entity) -> Result<Option<String>> {
+        let target_id: Option<Option<String>> = self
+            .connection
+            .query_row(
+                "SELECT target_id FROM entities WHERE target = ?1 AND repo = ?2 AND kind = ?3 AND source_id = ?4 AND status = ?5",
+                params![self.target, entity.repo, entity.kind.name(), entity.source_id, Status::Created.name()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|source| self.error(source))?;
+        Ok(target_id.flatten())
+    }
+
+    pub fn set_pending(&self, entity: &Entity) -> Result<()> {
+        self.set(entity, Status::Pending, None, None)
+    }
+
+    pub fn set_created(&self, entity: &Entity, created: &Created) -> Result<()> {
+        self.set(entity, Status::Created, Some(created), None)
+    }
+
+    pub fn set_failed(&self, entity: &Entity, error: &Error) -> Result<()> {
+        self.set(entity, Status::Failed, None, Some(&error.to_string()))
+    }
+
+    /// Every created entity of this target, by repository and kind.
+    pub fn mappings(&self) -> Result<Vec<Mapping>> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT kind, repo, source_id, source_url, target_id, target_url FROM entities
+                 WHERE target = ?1 AND status = ?2
+                 ORDER BY repo, kind DESC, length(source_id), source_id",
+            )
+            .map_err(|source| self.error(source))?;
+        let rows = statement
+            .query_map(params![self.target, Status::Created.name()], |row| {
+                Ok(Mapping {
+                    kind: row.get(0)?,
+                    repo: row.get(1)?,
+                    source_id: row.get(2)?,
+                    source_url: row.get(3)?,
+                    target_id: row.get(4)?,
+                    target_url: row.get(5)?,
+                })
+            })
+            .map_err(|source| self.error(source))?;
+        rows.collect::<rusqlite::Result<_>>().map_err(|source| self.error(source))
+    }
+
+    /// Drops everything recorded for `repo`, after it was deleted from the target.
+    pub fn forget_repository(&self, repo: &str) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM entities WHERE target = ?1 AND repo = ?2", params![self.target, repo])
+            .map_err(|source| self.error(source))?;
+        Ok(())
+    }
+
+    fn set(&self, entity: &Entity, status: Status, created: Option<&Created>, error: Option<&str>) -> Result<()> {
+        self.connection
+            .execute(
+                "INSERT INTO entities (target, repo, kind, source_id, status, target_id, target_url, source_url, error, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                 ON CONFLICT (target, repo, kind, source_id) DO UPDATE SET
+                     status = excluded.status, target_id = excluded.target_id, target_url = excluded.target_url,
+                     source_url = excluded.source_url, error = excluded.error, updated_at = excluded.updated_at",
+                params![
+                    self.target,
+                    entity.repo,
+                    entity.kind.name(),
+                    entity.source_id,
+                    status.name(),
+                    created.map(|created| &created.id),
+                    created.map(|created| &created.url),
+                    entity.source_url,
+                    error,
+                    chrono::Utc::now().to_rfc3339()
+                ],
+            )
+            .map_err(|source| self.error(source))?;
+        Ok(())
+    }
+
+    fn error(&self, source: rusqlite::Error) -> Error {
+        Error::State { path: self.path.clone(), source }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(dir: &Path) -> StateStore {
+        StateStore::open(&dir.join("state.sqlite"), "github", "link-foundation").unwrap()
+    }
+
+    fn issue(number: u64) -> Entity<'static> {
+        Entity { repo: "r", kind: Kind::Issue, source_id: number.to_string(), source_url: format!("https://github.com/o/r/issues/{}", number) }
+    }
+
+    fn created(id: &str) -> Created {
+        Created { id: id.to_string(), url: format!("https://github.com/link-foundation/r/issues/{}", id) }
+    }
+
+    #[test]
+    fn upgrades_a_version_1_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.sqlite");
+        let connection = Connection::open(&path).unwrap();
+        connection.execute_batch(&format!("{} PRAGMA user_version = 1;", MIGRATIONS[0])).unwrap();
+        connection
+            .execute(
+                "INSERT INTO entities (target, repo, kind, source_id, status, target_id, updated_at)
+                 VALUES ('github/link-foundation', 'r', 'issue', '1', 'created', '7', '2024-01-01T00:00:00Z')",
+                [],
+            )
+            .unwrap();
+        drop(connection);
+
+        let state = StateStore::open(&path, "github", "link-foundation").unwrap();
+        let version: usize = state.connection.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        let mappings = state.mappings().unwrap();
+        assert_eq!(mappings.len(), 1);
+        assert_eq!((mappings[0].target_id.as_deref(), mappings[0].target_url.as_deref()), (Some("7"), None));
+
+        // A newer schema is refused instead of being written to.
+        state.connection.execute_batch("PRAGMA user_version = 99;").unwrap();
+        assert!(matches!(StateStore::open(&path, "github", "link-foundation"), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn only_created_entities_count_as_created() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = store(dir.path());
+        assert_eq!(state.status(&issue(1)).unwrap(), None);
+
+        state.set_pending(&issue(1)).unwrap();
+        assert_eq!(state.status(&issue(1)).unwrap(), Some(Status::Pending));
+        assert!(!state.is_created(&issue(1)).unwrap());
+
+        state.set_failed(&issue(1), &Error::Config("boom".to_string())).unwrap();
+        assert_eq!(state.status(&issue(1)).unwrap(), Some(Status::Failed));
+        assert!(!state.is_created(&issue(1)).unwrap());
+        assert_eq!(state.target_id(&issue(1)).unwrap(), None);
+
+        state.set_created(&issue(1), &created("7")).unwrap();
+        assert!(state.is_created(&issue(1)).unwrap());
+        assert_eq!(state.target_id(&issue(1)).unwrap().as_deref(), Some("7"));
+    }
+
+    #[test]
+    fn recording_again_replaces_the_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = store(dir.path());
+        state.set_created(&issue(1), &created("7")).unwrap();
+        state.set_created(&issue(1), &created("8")).unwrap();
+
+        let mappings = state.mappings().unwrap();
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0].target_id.as_deref(), Some("8"));
+        assert_eq!(mappings[0].target_url.as_deref(), Some("https://github.com/link-foundation/r/issues/8"));
+        assert_eq!(mappings[0].source_url.as_deref(), Some("https://github.com/o/r/issues/1"));
+    }
+
+    #[test]
+    fn keeps_targets_and_repositories_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = store(dir.path());
+        let other = StateStore::open(&dir.path().join("state.sqlite"), "gitflic", "link-foundation").unwrap();
+        let elsewhere = Entity { repo: "s", ..issue(1) };
+        state.set_created(&issue(1), &created("7")).unwrap();
+        state.set_created(&elsewhere, &created("9")).unwrap();
+        assert_eq!(other.status(&issue(1)).unwrap(), None);
+
+        state.forget_repository("r").unwrap();
+        assert_eq!(state.status(&issue(1)).unwrap(), None);
+        assert!(state.is_created(&elsewhere).unwrap());
+    }
+
+    #[test]
+    fn reads_unknown_statuses_as_pending() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = store(dir.path());
+        state.set_created(&issue(1), &created("7")).unwrap();
+        state.connection.execute("UPDATE entities SET status = 'archived'", []).unwrap();
+        assert_eq!(state.status(&issue(1)).unwrap(), Some(Status::Pending));
+        assert_eq!(state.target_id(&issue(1)).unwrap(), None);
+    }
+}
@@ -0,0 +1,170 @@
+use octocrab::Octocrab;
+use serde_json::{json, Map, Value};
+
+use crate::cli::Backend;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::gitflic::{gitflicify_repository_name, GitFlic, GITFLIC_API_HOST};
+use crate::github;
+use crate::models::Repository;
+use crate::pacing::Pacer;
+use crate::settings;
+
+const GITHUB_API_HOST: &str = "api.github.com";
+
+/// The forge the target organization lives on, with the pacing of writes to it.
+pub struct Target {
+    forge: Forge,
+    pacer: Pacer,
+}
+
+/// A repository created on the target, with the source settings it did not take.
+pub struct NewRepository {
+    pub id: String,
+    pub unapplied: Vec<String>,
+}
+
+enum Forge {
+    GitHub(Octocrab),
+    GitFlic(GitFlic),
+}
+
+impl Target {
+    pub fn new(config: &Config) -> Result<Self> {
+        match config.backend {
+            Backend::Github => Ok(Target {
+                forge: Forge::GitHub(github::client(config.github_access_token()?)?),
+                pacer: Pacer::new(GITHUB_API_HOST, &config.pacing),
+            }),
+            Backend::Gitflic => Ok(Target {
+                forge: Forge::GitFlic(GitFlic::new(config.gitflic_access_token()?)),
+                pacer: Pacer::new(GITFLIC_API_HOST, &config.pacing),
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match &self.forge {
+            Forge::GitHub(_) => "GitHub",
+            Forge::GitFlic(_) => "GitFlic",
+        }
+    }
+
+    /// Pause after every creation so the forge does not throttle us.
+    pub async fn pause(&self) {
+        self.pacer.pause().await
+    }
+
+    /// HTTPS URL of the repository, for git.
+    pub fn git_url(&self, organization: &str, repo_name: &str) -> String {
+        match &self.forge {
+            Forge::GitHub(_) => format!("https://github.com/{}/{}.git", organization, repo_name),
+            Forge::GitFlic(_) => format!("https://gitflic.ru/project/{}/{}.git", organization, gitflicify_repository_name(repo_name)),
+        }
+    }
+
+    /// Web page of the repository.
+    pub fn web_url(&self, organization: &str, repo_name: &str) -> String {
+        match &self.forge {
+            Forge::GitHub(_) => format!("https://github.com/{}/{}", organization, repo_name),
+            Forge::GitFlic(_) => format!("https://gitflic.ru/project/{}/{}", organization, gitflicify_repository_name(repo_name)),
+        }
+    }
+
+    pub fn github(&self) -> Result<&Octocrab> {
+        match &self.forge {
+            Forge::GitHub(octocrab) => Ok(octocrab),
+            Forge::GitFlic(_) => Err(Error::Config("This operation is only supported for the github backend.".to_string())),
+        }
+    }
+
+    pub async fn repository_exists(&self, organization: &str, repo_name: &str) -> Result<bool> {
+        Ok(self.repository_id(organization, repo_name).await?.is_some())
+    }
+
+    /// The id the target gave the repository, the same kind of id `create_repository`
+    /// returns, or `None` if it does not exist.
+    pub async fn repository_id(&self, organization: &str, repo_name: &str) -> Result<Option<String>> {
+        match &self.forge {
+            Forge::GitHub(octocrab) => match github::get::<Value>(octocrab, &format!("repos/{}/{}", organization, repo_name)).await {
+                Ok(repository) => Ok(Some(github::id_of(&repository))),
+                Err(Error::Http { status: 404, .. }) => Ok(None),
+                Err(error) => Err(error),
+            },
+            Forge::GitFlic(gitflic) => {
+                // GitFlic throttles lookups as well as creations.
+                let id = gitflic.project_id(organization, repo_name).await;
+                self.pacer.pause().await;
+                id
+            }
+        }
+    }
+
+    /// Creates the repository with every setting of the source the target takes on
+    /// creation. The default branch and the archived state need the code to be pushed
+    /// first and are left to `apply_settings`.
+    pub async fn create_repository(&self, organization: &str, repo: &Repository) -> Result<NewRepository> {
+        match &self.forge {
+            Forge::GitHub(octocrab) => {
+                let settings = settings::github_settings(repo);
+                let mut body = settings.clone();
+                body.insert("name".to_string(), json!(repo.name));
+                let route = format!("orgs/{}/repos", organization);
+                let created: Value = match github::post(octocrab, &route, &body).await {
+                    // Only organizations on GitHub Enterprise have internal repositories.
+                    Err(Error::Http { status: 422, .. }) if repo.visibility.as_deref() == Some("internal") => {
+                        body.insert("visibility".to_string(), json!("private"));
+                        github::post(octocrab, &route, &body).await?
+                    }
+                    result => result?,
+                };
+                let mut unapplied = settings::unapplied(&settings, &created);
+                unapplied.extend(settings::apply_topics(octocrab, organization, repo).await);
+                Ok(NewRepository { id: github::id_of(&created), unapplied })
+            }
+            Forge::GitFlic(gitflic) => {
+                let id = gitflic.create_project(organization, repo).await?;
+                Ok(NewRepository { id, unapplied: settings::unsupported_by_gitflic(repo) })
+            }
+        }
+    }
+
+    /// Brings an existing repository in line with the source: every setting, the default
+    /// branch once it has been pushed, and, last, the archived state, which makes the
+    /// repository read-only. Returns the settings that could not be applied.
+    pub async fn apply_settings(&self, organization: &str, repo: &Repository) -> Result<Vec<String>> {
+        let octocrab = self.github()?;
+        let route = format!("repos/{}/{}", organization, repo.name);
+        let current: Value = github::get(octocrab, &route).await?;
+        if current["archived"] == json!(true) {
+            return Ok(if repo.archived { Vec::new() } else { vec!["every setting (the repository is archived on the target)".to_string()] });
+        }
+
+        let mut settings = settings::github_settings(repo);
+        let mut unapplied = Vec::new();
+        if let Some(branch) = &repo.default_branch {
+            if github::exists(octocrab, &format!("{}/branches/{}", route, branch)).await? {
+                settings.insert("default_branch".to_string(), json!(branch));
+            } else {
+                unapplied.push(format!("default_branch ({} has not been pushed)", branch));
+            }
+        }
+        let updated: Value = github::patch(octocrab, &route, &settings).await?;
+        unapplied.extend(settings::unapplied(&settings, &updated));
+        unapplied.extend(settings::apply_topics(octocrab, organization, repo).await);
+
+        if repo.archived {
+            let mut archive = Map::new();
+            archive.insert("archived".to_string(), json!(true));
+            let archived: Value = github::patch(octocrab, &route, &archive).await?;
+            unapplied.extend(settings::unapplied(&archive, &archived));
+        }
+
+        Ok(unapplied)
+    }
+
+    pub async fn delete_repository(&self, organization: &str, repo_name: &str) -> Result<()> {
+        let octocrab = self.github()?;
+        github::delete(octocrab, &format!("repos/{}/{}", organization, repo_name)).await
+    }
+}
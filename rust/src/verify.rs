@@ -0,0 +1,25 @@
+
+use crate::data::DataDir;
+use crate::error::{Error, Failures, Result};
+use crate::target::Target;
+
+/// Reports every fetched repository that is missing from the target organization.
+pub async fn run(target: &Target, target_organization: &str, data: &DataDir) -> Result<Failures> {
+    let repos = data.read_repositories()?;
+
+    let mut failures = Vec::new();
+    for repo in &repos {
+        match target.repository_exists(target_organization, &repo.name).await {
+            Ok(true) => println!("Repository {} exists on {}.", repo.name, target.name()),
+            Ok(false) => {
+                let message = format!("repository {} is missing in {} on {}", repo.name, target_organization, target.name());
+                failures.push((repo.name.clone(), Error::Verification(message)));
+            }
+            Err(error) => failures.push((repo.name.clone(), error)),
+        }
+    }
+
+    println!("Verified {} repositories, {} failed.", repos.len(), failures.len());
+
+    Ok(failures)
+}